@@ -1,37 +1,246 @@
-
+use std::borrow::{Borrow, Cow};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet};
+use std::env;
 use std::error::Error;
 use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, Read, Write};
 use std::num::{ParseFloatError, ParseIntError};
-use std::str::FromStr;
+use std::ops::{Range, RangeBounds, RangeInclusive};
+use std::path::Path;
+use std::str::{FromStr, Split, Utf8Error};
+use std::sync::Arc;
 
 // This is the custom error type that we will be using for the parser for
-// `Climate`.
-#[derive(Debug, PartialEq)]
+// `Climate`. New variants keep being added, so it is `non_exhaustive`;
+// code outside this crate that only needs the category of an error can
+// match on `kind()` instead.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 enum ParseClimateError {
+    Empty,
+    // `found` is the number of fields the record was split into, and
+    // `expected` the numbers of fields it may have.
+    BadLen {
+        expected: RangeInclusive<usize>,
+        found: usize,
+    },
+    NoCity,
+    // `field` is the text that failed to parse as a year.
+    ParseInt {
+        source: ParseIntError,
+        field: String,
+    },
+    // `field` is the text that failed to parse as a temperature.
+    ParseFloat {
+        source: ParseFloatError,
+        field: String,
+    },
+    // `field` is the text that failed to parse as a humidity.
+    ParseHumidity {
+        source: ParseFloatError,
+        field: String,
+    },
+    BadDelimiter(char),
+    UnterminatedQuote,
+    NotFinite,
+    // `year` is wide enough for a year that `Climate::shift_year` computed
+    // outside the range of `u32`.
+    YearOutOfRange {
+        year: i64,
+        min: u32,
+        max: u32,
+    },
+    UnknownUnit(char),
+    BelowAbsoluteZero,
+    MissingYear,
+    MissingTemp,
+    UnknownColumn(String),
+    MissingColumn(&'static str),
+    HumidityOutOfRange(f32),
+    InvalidUtf8(Utf8Error),
+    // A key that a JSON record must have is absent.
+    MissingField(&'static str),
+    // The text isn't a JSON object of the expected shape; holds the JSON
+    // parser's message.
+    InvalidJson(String),
+    // A number used a thousands separator, which is never accepted.
+    ThousandsSeparator(char),
+    // A required header row is missing, or doesn't name the `city`, `year`
+    // and `temp` columns; holds the offending line.
+    BadHeader(String),
+    // The temperature lies outside `Climate::MIN_TEMP..=Climate::MAX_TEMP`.
+    TempOutOfRange(f32),
+    // With `ParseOptions::canonical_numbers`, a year field with a leading
+    // `+` or leading zeros.
+    NonCanonicalYear(String),
+    // With `ParseOptions::canonical_numbers`, a temperature field with a
+    // leading `+`.
+    NonCanonicalTemp(String),
+}
+
+// The category of a `ParseClimateError`, without its payload. There is a
+// kind for each variant, with the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+enum ParseClimateErrorKind {
     Empty,
     BadLen,
     NoCity,
-    ParseInt(ParseIntError),
-    ParseFloat(ParseFloatError),
+    ParseInt,
+    ParseFloat,
+    ParseHumidity,
+    BadDelimiter,
+    UnterminatedQuote,
+    NotFinite,
+    YearOutOfRange,
+    UnknownUnit,
+    BelowAbsoluteZero,
+    MissingYear,
+    MissingTemp,
+    UnknownColumn,
+    MissingColumn,
+    HumidityOutOfRange,
+    InvalidUtf8,
+    MissingField,
+    InvalidJson,
+    ThousandsSeparator,
+    BadHeader,
+    TempOutOfRange,
+    NonCanonicalYear,
+    NonCanonicalTemp,
 }
 
-// This `From` implementation allows the `?` operator to work on
-// `ParseIntError` values.
-impl From<ParseIntError> for ParseClimateError {
-    fn from(e: ParseIntError) -> Self {
-        Self::ParseInt(e)
+impl ParseClimateError {
+    pub fn kind(&self) -> ParseClimateErrorKind {
+        use ParseClimateError::*;
+        match self {
+            Empty => ParseClimateErrorKind::Empty,
+            BadLen { .. } => ParseClimateErrorKind::BadLen,
+            NoCity => ParseClimateErrorKind::NoCity,
+            ParseInt { .. } => ParseClimateErrorKind::ParseInt,
+            ParseFloat { .. } => ParseClimateErrorKind::ParseFloat,
+            ParseHumidity { .. } => ParseClimateErrorKind::ParseHumidity,
+            BadDelimiter(_) => ParseClimateErrorKind::BadDelimiter,
+            UnterminatedQuote => ParseClimateErrorKind::UnterminatedQuote,
+            NotFinite => ParseClimateErrorKind::NotFinite,
+            YearOutOfRange { .. } => ParseClimateErrorKind::YearOutOfRange,
+            UnknownUnit(_) => ParseClimateErrorKind::UnknownUnit,
+            BelowAbsoluteZero => ParseClimateErrorKind::BelowAbsoluteZero,
+            MissingYear => ParseClimateErrorKind::MissingYear,
+            MissingTemp => ParseClimateErrorKind::MissingTemp,
+            UnknownColumn(_) => ParseClimateErrorKind::UnknownColumn,
+            MissingColumn(_) => ParseClimateErrorKind::MissingColumn,
+            HumidityOutOfRange(_) => ParseClimateErrorKind::HumidityOutOfRange,
+            InvalidUtf8(_) => ParseClimateErrorKind::InvalidUtf8,
+            MissingField(_) => ParseClimateErrorKind::MissingField,
+            InvalidJson(_) => ParseClimateErrorKind::InvalidJson,
+            ThousandsSeparator(_) => ParseClimateErrorKind::ThousandsSeparator,
+            BadHeader(_) => ParseClimateErrorKind::BadHeader,
+            TempOutOfRange(_) => ParseClimateErrorKind::TempOutOfRange,
+            NonCanonicalYear(_) => ParseClimateErrorKind::NonCanonicalYear,
+            NonCanonicalTemp(_) => ParseClimateErrorKind::NonCanonicalTemp,
+        }
+    }
+
+    // Whether the error only concerns the one record, so that a caller
+    // reading many records can skip it and continue. The others, such as
+    // `Empty`, `BadLen` or a bad header, suggest the input isn't in the
+    // expected format at all, and reading it should be aborted.
+    pub fn is_recoverable(&self) -> bool {
+        use ParseClimateError::*;
+        match self {
+            Empty
+            | BadLen { .. }
+            | BadDelimiter(_)
+            | UnknownColumn(_)
+            | MissingColumn(_)
+            | BadHeader(_)
+            | InvalidUtf8(_)
+            | InvalidJson(_) => false,
+            NoCity
+            | ParseInt { .. }
+            | ParseFloat { .. }
+            | ParseHumidity { .. }
+            | UnterminatedQuote
+            | NotFinite
+            | YearOutOfRange { .. }
+            | UnknownUnit(_)
+            | BelowAbsoluteZero
+            | MissingYear
+            | MissingTemp
+            | HumidityOutOfRange(_)
+            | MissingField(_)
+            | ThousandsSeparator(_)
+            | TempOutOfRange(_)
+            | NonCanonicalYear(_)
+            | NonCanonicalTemp(_) => true,
+        }
     }
 }
 
-// This `From` implementation allows the `?` operator to work on
-// `ParseFloatError` values.
-impl From<ParseFloatError> for ParseClimateError {
-    fn from(e: ParseFloatError) -> Self {
-        Self::ParseFloat(e)
+// These take the place of `From` implementations for the number parsing
+// errors, which couldn't record the field that failed.
+impl ParseClimateError {
+    fn parse_int(source: ParseIntError, field: &str) -> Self {
+        Self::ParseInt {
+            source,
+            field: field.to_string(),
+        }
+    }
+
+    fn parse_float(source: ParseFloatError, field: &str) -> Self {
+        Self::ParseFloat {
+            source,
+            field: field.to_string(),
+        }
+    }
+
+    fn parse_humidity(source: ParseFloatError, field: &str) -> Self {
+        Self::ParseHumidity {
+            source,
+            field: field.to_string(),
+        }
     }
 }
 
-// impl Error for ParseClimateError {}
+// The `Error` trait lets `ParseClimateError` be boxed into a
+// `Box<dyn Error>`. Overriding `source()` keeps the underlying parse error
+// reachable for code that walks the error chain.
+impl Error for ParseClimateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        use ParseClimateError::*;
+        match self {
+            Empty
+            | BadLen { .. }
+            | NoCity
+            | BadDelimiter(_)
+            | UnterminatedQuote
+            | NotFinite
+            | YearOutOfRange { .. }
+            | UnknownUnit(_)
+            | BelowAbsoluteZero
+            | MissingYear
+            | MissingTemp
+            | UnknownColumn(_)
+            | MissingColumn(_)
+            | HumidityOutOfRange(_)
+            | MissingField(_)
+            | InvalidJson(_)
+            | ThousandsSeparator(_)
+            | BadHeader(_)
+            | TempOutOfRange(_)
+            | NonCanonicalYear(_)
+            | NonCanonicalTemp(_) => None,
+            ParseInt { source, .. } => Some(source),
+            ParseFloat { source, .. } => Some(source),
+            ParseHumidity { source, .. } => Some(source),
+            InvalidUtf8(e) => Some(e),
+        }
+    }
+}
 
 // The `Display` trait allows for other code to obtain the error formatted
 // as a user-visible string.
@@ -39,57 +248,7462 @@ impl Display for ParseClimateError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         // Imports the variants to make the following code more compact.
         use ParseClimateError::*;
+        // There is deliberately no catch-all arm here: when a new variant is
+        // added, the compiler should refuse to build until it has a message.
         match self {
             Empty => write!(f, "empty input"),
-            BadLen => write!(f, "incorrect number of fields"),
+            BadLen { expected, found } => {
+                let (min, max) = (expected.start(), expected.end());
+                write!(f, "incorrect number of fields: expected {}", min)?;
+                if max > min {
+                    let sep = if *max == min + 1 { "or" } else { "to" };
+                    write!(f, " {} {}", sep, max)?;
+                }
+                write!(f, ", found {}", found)
+            }
             NoCity => write!(f, "no city name"),
-            ParseInt(_e) => write!(f, "error parsing year: invalid digit found in string"),
-            ParseFloat(e) => write!(f, "error parsing temperature: {}", e),
-            _ => write!(f, "unhandled error!"),
+            ParseInt { source, field } => {
+                write!(f, "error parsing year {:?}: {}", field, source)
+            }
+            ParseFloat { source, field } => {
+                write!(f, "error parsing temperature {:?}: {}", field, source)
+            }
+            ParseHumidity { source, field } => {
+                write!(f, "error parsing humidity {:?}: {}", field, source)
+            }
+            BadDelimiter(c) => write!(f, "{:?} cannot be used as a field delimiter", c),
+            UnterminatedQuote => write!(f, "unterminated quote in city name"),
+            NotFinite => write!(f, "temperature is not a finite number"),
+            YearOutOfRange { year, min, max } => {
+                write!(f, "year {} is outside the range {}..={}", year, min, max)
+            }
+            UnknownUnit(c) => write!(f, "unknown temperature unit {:?}", c),
+            BelowAbsoluteZero => write!(f, "temperature is below absolute zero"),
+            MissingYear => write!(f, "year field is empty"),
+            MissingTemp => write!(f, "temperature field is empty"),
+            UnknownColumn(name) => write!(f, "unknown column {:?} in header", name),
+            MissingColumn(name) => write!(f, "header has no {:?} column", name),
+            HumidityOutOfRange(h) => write!(f, "humidity {} is outside the range 0..=100", h),
+            InvalidUtf8(e) => write!(f, "record is not valid UTF-8: {}", e),
+            MissingField(name) => write!(f, "missing field {:?}", name),
+            InvalidJson(e) => write!(f, "invalid JSON record: {}", e),
+            ThousandsSeparator(c) => write!(f, "thousands separator {:?} in a number", c),
+            TempOutOfRange(t) => write!(
+                f,
+                "temperature {} is outside the plausible range {}..={}",
+                t,
+                Climate::MIN_TEMP,
+                Climate::MAX_TEMP
+            ),
+            NonCanonicalYear(year) => {
+                write!(f, "year {:?} has a leading '+' or leading zeros", year)
+            }
+            NonCanonicalTemp(temp) => write!(f, "temperature {:?} has a leading '+'", temp),
+            BadHeader(line) => write!(
+                f,
+                "bad header {:?}: expected city, year and temp columns",
+                line
+            ),
         }
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 struct Climate {
     city: String,
     year: u32,
     temp: f32,
+    // Relative humidity in percent, from an optional fourth field.
+    humidity: Option<f32>,
 }
 
-// Parser for `Climate`.
-// 1. Split the input string into 3 fields: city, year, temp.
-// 2. Return an error if the string is empty or has the wrong number of
-//    fields.
-// 3. Return an error if the city name is empty.
-// 4. Parse the year as a `u32` and return an error if that fails.
-// 5. Parse the temp as a `f32` and return an error if that fails.
-// 6. Return an `Ok` value containing the completed `Climate` value.
-impl FromStr for Climate {
-    type Err = ParseClimateError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
+// An empty city, the year 2000 and 0 degrees, with no humidity. This is a
+// placeholder for filling in field by field: the empty city means
+// `Climate::new` would reject it, so a default record is not valid as is.
+impl Default for Climate {
+    fn default() -> Self {
+        Climate {
+            city: String::new(),
+            year: 2000,
+            temp: 0.0,
+            humidity: None,
+        }
+    }
+}
+
+// `Climate::new` and the parser never produce a NaN temperature, so the
+// derived `PartialEq` is reflexive for every value built through them.
+impl Eq for Climate {}
+
+// Orders by city, then year, then temperature (then humidity, with no
+// humidity first), so sorting a `Vec<Climate>` groups each city's records
+// in chronological order. `f32::total_cmp` makes the order total; note that
+// it ranks `-0.0` below `0.0`.
+impl Ord for Climate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.city
+            .cmp(&other.city)
+            .then_with(|| self.year.cmp(&other.year))
+            .then_with(|| self.temp.total_cmp(&other.temp))
+            .then_with(|| match (self.humidity, other.humidity) {
+                (Some(a), Some(b)) => a.total_cmp(&b),
+                (a, b) => a.is_some().cmp(&b.is_some()),
+            })
+    }
+}
+
+impl PartialOrd for Climate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// The city, year, temp and optional humidity fields of a record, as split
+// off by `Climate::split_record`.
+type RecordFields<'a> = (Cow<'a, str>, &'a str, &'a str, Option<&'a str>);
+
+impl Climate {
+    // The range of years accepted for an observation unless
+    // `ParseOptions::years` says otherwise.
+    pub const MIN_YEAR: u32 = 1700;
+    pub const MAX_YEAR: u32 = 2100;
+
+    // The pivot for `parse_expand_2digit`: a two-digit year up to and
+    // including this one is in the 2000s, and a later one in the 1900s.
+    pub const TWO_DIGIT_PIVOT: u32 = 30;
+
+    // The range of plausible surface temperatures in degrees Celsius,
+    // rounded out from the lowest and highest ever recorded (-89.2 and
+    // 56.7). Only `parse_strict_temp` enforces it.
+    pub const MIN_TEMP: f32 = -90.0;
+    pub const MAX_TEMP: f32 = 60.0;
+
+    // Builds a `Climate` from already-typed values, applying the same
+    // validation as the parser: the city must not be empty, the year must
+    // lie within `MIN_YEAR..=MAX_YEAR` and the temperature must be finite
+    // and not below absolute zero. The humidity is left unset.
+    pub fn new(
+        city: impl Into<String>,
+        year: u32,
+        temp: f32,
+    ) -> Result<Climate, ParseClimateError> {
+        Climate::new_with_years(city, year, temp, &(Climate::MIN_YEAR..=Climate::MAX_YEAR))
+    }
+
+    // Like `new`, but accepting any year in `years`.
+    fn new_with_years(
+        city: impl Into<String>,
+        year: u32,
+        temp: f32,
+        years: &RangeInclusive<u32>,
+    ) -> Result<Climate, ParseClimateError> {
+        let city = city.into();
+        check_city(&city)?;
+        let year = check_year(year, years)?;
+        let temp = check_temp(temp)?;
+        Ok(Climate {
+            city,
+            year,
+            temp,
+            humidity: None,
+        })
+    }
+
+    // Whether the temperature lies within `MIN_TEMP..=MAX_TEMP`.
+    pub fn is_plausible_temp(&self) -> bool {
+        (Climate::MIN_TEMP..=Climate::MAX_TEMP).contains(&self.temp)
+    }
+
+    pub fn builder() -> ClimateBuilder {
+        ClimateBuilder::new()
+    }
+
+    // Splits the record into its city, year and temperature, the inverse
+    // of `Climate::try_from((city, year, temp))`. The humidity is dropped.
+    pub fn into_parts(self) -> (String, u32, f32) {
+        (self.city, self.year, self.temp)
+    }
+
+    // Replaces the year, which must lie within `MIN_YEAR..=MAX_YEAR`.
+    pub fn with_year(self, year: u32) -> Result<Climate, ParseClimateError> {
+        Ok(Climate {
+            year: check_year(year, &(Climate::MIN_YEAR..=Climate::MAX_YEAR))?,
+            ..self
+        })
+    }
+
+    // A copy of the record moved `delta` years later, or earlier if it is
+    // negative. A result outside `MIN_YEAR..=MAX_YEAR` is `YearOutOfRange`,
+    // carrying the year that was asked for even if it isn't a `u32`.
+    pub fn shift_year(&self, delta: i32) -> Result<Climate, ParseClimateError> {
+        match self.year.checked_add_signed(delta) {
+            Some(year) => self.clone().with_year(year),
+            None => Err(ParseClimateError::YearOutOfRange {
+                year: i64::from(self.year) + i64::from(delta),
+                min: Climate::MIN_YEAR,
+                max: Climate::MAX_YEAR,
+            }),
+        }
+    }
+
+    // Replaces the temperature, with the same checks as `new`.
+    pub fn with_temp(self, temp: f32) -> Result<Climate, ParseClimateError> {
+        Ok(Climate {
+            temp: check_temp(temp)?,
+            ..self
+        })
+    }
+
+    // Sets the relative humidity, which must lie within `0.0..=100.0`.
+    pub fn with_humidity(self, humidity: f32) -> Result<Climate, ParseClimateError> {
+        Ok(Climate {
+            humidity: Some(check_humidity(humidity)?),
+            ..self
+        })
+    }
+
+    // Parses a `Climate` with fields separated by `delim` and otherwise
+    // strict options.
+    pub fn parse_with_delimiter(s: &str, delim: char) -> Result<Climate, ParseClimateError> {
+        let options = ParseOptions {
+            delimiter: delim,
+            ..ParseOptions::default()
+        };
+        Climate::parse_with_options(s, &options)
+    }
+
+    // Parses a record in the common European export format, with `;`
+    // between fields and a decimal comma, e.g. `Berlin;2015;7,3`.
+    pub fn parse_euro_locale(s: &str) -> Result<Climate, ParseClimateError> {
+        let options = ParseOptions {
+            delimiter: ';',
+            number_format: NumberFormat::DecimalComma,
+            ..ParseOptions::default()
+        };
+        Climate::parse_with_options(s, &options)
+    }
+
+    // Parser for `Climate`.
+    // 1. Reject delimiters that can appear inside the year or temp fields.
+    // 2. Split the input string into 3 or 4 fields: city, year, temp and
+    //    an optional humidity. The city may be quoted, see `split_fields`.
+    //    If enabled in `options`, drop a single trailing delimiter and trim
+    //    surrounding whitespace from each field.
+    // 3. Return an error if the string is empty or has the wrong number of
+    //    fields.
+    // 4. Return an error if the city name is empty.
+    // 5. Parse the year as a `u32` and return an error if that fails or if
+    //    it is outside `options.years`.
+    // 6. Parse the temp as a `f32`, see `parse_temp`, and return an error if
+    //    that fails, if it is NaN or infinite, or if it is below absolute
+    //    zero.
+    // 7. If there is a humidity field, parse it as a `f32` and return an
+    //    error if that fails or if it is outside `0.0..=100.0`.
+    // 8. Return an `Ok` value containing the completed `Climate` value.
+    pub fn parse_with_options(
+        s: &str,
+        options: &ParseOptions,
+    ) -> Result<Climate, ParseClimateError> {
+        let (city, year, temp, humidity) = Climate::split_record(s, options)?;
+        if options.canonical_numbers {
+            check_canonical(year, temp)?;
+        }
+        let (raw_temp, raw_humidity) = (temp, humidity);
+        let temp = normalize_number(temp, options.number_format)?;
+        let humidity = humidity
+            .map(|h| normalize_number(h, options.number_format))
+            .transpose()?;
+        Climate::from_fields(city, year, &temp, humidity.as_deref(), &options.years).map_err(|e| {
+            let rewritten = humidity.as_deref().zip(raw_humidity);
+            restore_number_field(e, [(&*temp, raw_temp)].into_iter().chain(rewritten))
+        })
+    }
+
+    // Like `FromStr`, but instead of stopping at the first bad field, checks
+    // all of them and returns every problem in field order. Errors in the
+    // structure of the record are still reported on their own, since the
+    // fields can't be told apart then.
+    pub fn parse_all_errors(s: &str) -> Result<Climate, Vec<ParseClimateError>> {
+        let options = ParseOptions::default();
+        let (city, year, temp, humidity) =
+            Climate::split_record(s, &options).map_err(|e| vec![e])?;
+        let checked_city = check_city(&city);
+        let year = parse_year(year, &options.years);
+        let temp = parse_temp(temp).and_then(check_temp);
+        let humidity = humidity.map(parse_humidity).transpose();
+        match (checked_city, year, temp, humidity) {
+            (Ok(()), Ok(year), Ok(temp), Ok(humidity)) => Ok(Climate {
+                city: city.into_owned(),
+                year,
+                temp,
+                humidity,
+            }),
+            (city, year, temp, humidity) => {
+                Err([city.err(), year.err(), temp.err(), humidity.err()]
+                    .into_iter()
+                    .flatten()
+                    .collect())
+            }
+        }
+    }
+
+    // Like `FromStr`, but also rejects a temperature that isn't plausible,
+    // see `is_plausible_temp`.
+    pub fn parse_strict_temp(s: &str) -> Result<Climate, ParseClimateError> {
+        let climate: Climate = s.parse()?;
+        if !climate.is_plausible_temp() {
+            return Err(ParseClimateError::TempOutOfRange(climate.temp));
+        }
+        Ok(climate)
+    }
+
+    // Parses a record whose temperature may carry a `C` or `F` unit
+    // suffix, converting it to Celsius. A bare number is taken to be
+    // Celsius, and any other suffix, such as the `K` in `30.1K`, is
+    // `UnknownUnit`. This is what `FromStr` does too, see
+    // `TempUnit::from_suffix`.
+    pub fn parse_with_unit_suffix(s: &str) -> Result<Climate, ParseClimateError> {
+        s.parse()
+    }
+
+    // Like `FromStr`, but a year written with one or two digits is
+    // expanded around `TWO_DIGIT_PIVOT` before the range check, so `99` is
+    // read as 1999 and `05` as 2005. `FromStr` rejects such years as out
+    // of range.
+    pub fn parse_expand_2digit(s: &str) -> Result<Climate, ParseClimateError> {
+        let options = ParseOptions::default();
+        let (city, year, temp, humidity) = Climate::split_record(s, &options)?;
+        check_city(&city)?;
+        let year = match year.parse::<u32>() {
+            Ok(short) if year.len() <= 2 && year.bytes().all(|b| b.is_ascii_digit()) => {
+                let century = if short <= Climate::TWO_DIGIT_PIVOT {
+                    2000
+                } else {
+                    1900
+                };
+                check_year(century + short, &options.years)?
+            }
+            _ => parse_year(year, &options.years)?,
+        };
+        let temp = check_temp(parse_temp(temp)?)?;
+        let humidity = humidity.map(parse_humidity).transpose()?;
+        Ok(Climate {
+            city: city.into_owned(),
+            year,
+            temp,
+            humidity,
+        })
+    }
+
+    // Like `FromStr`, but a temperature field that is empty or `NA` is
+    // taken to be a missing reading instead of an error.
+    pub fn parse_allow_missing(s: &str) -> Result<ClimateMaybe, ParseClimateError> {
+        let options = ParseOptions::default();
+        let (city, year, temp, humidity) = Climate::split_record(s, &options)?;
+        check_city(&city)?;
+        let year = parse_year(year, &options.years)?;
+        let temp = match temp {
+            "" | "NA" => None,
+            temp => Some(check_temp(parse_temp(temp)?)?),
+        };
+        let humidity = humidity.map(parse_humidity).transpose()?;
+        Ok(ClimateMaybe {
+            city: city.into_owned(),
+            year,
+            temp,
+            humidity,
+        })
+    }
+
+    // Like `FromStr`, but a bad field is reported with its byte range in
+    // `s`, e.g. `6..10` for the `19x9` in `Paris,19x9,25.7`. The city's
+    // range runs up to the delimiter before the year, so it includes any
+    // quotes.
+    pub fn parse_spanned(s: &str) -> Result<Climate, SpannedError> {
+        let options = ParseOptions::default();
+        let (city, year, temp, humidity) = Climate::split_record(s, &options)
+            .map_err(|error| SpannedError { error, span: None })?;
+        // The fields are slices of `s`, so their offsets follow from their
+        // addresses.
+        let span_of = |field: &str| {
+            let start = field.as_ptr() as usize - s.as_ptr() as usize;
+            Some((start, start + field.len()))
+        };
+        let spanned = |span| move |error| SpannedError { error, span };
+        let city_end = span_of(year).map(|(start, _)| start - options.delimiter.len_utf8());
+        check_city(&city).map_err(spanned(city_end.map(|end| (0, end))))?;
+        let year = parse_year(year, &options.years).map_err(spanned(span_of(year)))?;
+        let temp = parse_temp(temp)
+            .and_then(check_temp)
+            .map_err(spanned(span_of(temp)))?;
+        let humidity = match humidity {
+            Some(field) => Some(parse_humidity(field).map_err(spanned(span_of(field)))?),
+            None => None,
+        };
+        Ok(Climate {
+            city: city.into_owned(),
+            year,
+            temp,
+            humidity,
+        })
+    }
+
+    // Parses a fixed-width record laid out as in `spec`. Each field is
+    // trimmed of its padding before being validated as in `FromStr`. A line
+    // that ends before the last column of `spec` is `BadLen`, counting the
+    // fields that it does hold completely.
+    pub fn from_fixed_width(
+        line: &str,
+        spec: &FixedWidthSpec,
+    ) -> Result<Climate, ParseClimateError> {
+        let ranges = [&spec.city, &spec.year, &spec.temp];
+        let len = line.chars().count();
+        if ranges.iter().any(|r| r.end > len) {
+            let found = ranges.iter().filter(|r| r.end <= len).count();
+            return Err(ParseClimateError::BadLen {
+                expected: 3..=3,
+                found,
+            });
+        }
+        // Columns are characters, so they are mapped to byte offsets to
+        // slice on character boundaries.
+        let offset = |column: usize| {
+            line.char_indices()
+                .nth(column)
+                .map_or(line.len(), |(i, _)| i)
+        };
+        let [city, year, temp] = ranges.map(|r| line[offset(r.start)..offset(r.end)].trim());
+        Climate::from_fields(
+            Cow::Borrowed(city),
+            year,
+            temp,
+            None,
+            &(Climate::MIN_YEAR..=Climate::MAX_YEAR),
+        )
+    }
+
+    // Steps 1 to 3 of `parse_with_options`: splits a record into its city,
+    // year, temp and optional humidity fields.
+    fn split_record<'a>(
+        s: &'a str,
+        options: &ParseOptions,
+    ) -> Result<RecordFields<'a>, ParseClimateError> {
+        let delim = options.delimiter;
+        check_delimiter(delim, options.number_format)?;
         if s.is_empty() {
             return Err(ParseClimateError::Empty);
         }
-        let splitted_item: Vec<_> = s.split(',').collect();
-        let (city, year, temp) = match &splitted_item[..] {
-            [city, year, temp] => (city.to_string(), year, temp),
-            _ => return Err(ParseClimateError::BadLen),
+        let trim = |field: &'a str| -> &'a str {
+            if options.trim_fields {
+                field.trim()
+            } else {
+                field
+            }
+        };
+        // The fields are pulled straight off the iterator rather than
+        // collected, so that parsing doesn't allocate anything besides the
+        // city. Any fields past the expected ones are only counted.
+        let (city, mut rest) = split_fields(s, delim)?;
+        let mut fields = [(); 4].map(|_| rest.next().map(trim));
+        let more = rest.count();
+        if options.allow_trailing_delimiter && more == 0 {
+            if let Some(last) = fields.iter_mut().rev().find(|f| f.is_some()) {
+                if *last == Some("") {
+                    *last = None;
+                }
+            }
+        }
+        // Counted after the trailing delimiter is dropped, so it matches the
+        // fields the record is read as.
+        let found = 1 + fields.iter().flatten().count() + more;
+        let (year, temp, humidity) = match fields {
+            [Some(year), Some(temp), humidity, None] => (year, temp, humidity),
+            _ => {
+                return Err(ParseClimateError::BadLen {
+                    expected: 3..=4,
+                    found,
+                })
+            }
+        };
+        let city = match city {
+            Cow::Borrowed(city) => Cow::Borrowed(trim(city)),
+            Cow::Owned(city) if options.trim_fields => Cow::Owned(city.trim().to_string()),
+            city => city,
+        };
+        Ok((city, year, temp, humidity))
+    }
+
+    // Validates and converts the already-split fields of a record.
+    fn from_fields(
+        city: Cow<'_, str>,
+        year: &str,
+        temp: &str,
+        humidity: Option<&str>,
+        years: &RangeInclusive<u32>,
+    ) -> Result<Climate, ParseClimateError> {
+        ClimateRef::from_fields(city, year, temp, humidity, years).map(ClimateRef::into_owned)
+    }
+
+    // The stored `temp` is in degrees Celsius.
+    pub fn to_celsius(&self) -> f32 {
+        self.temp
+    }
+
+    pub fn to_fahrenheit(&self) -> f32 {
+        TempUnit::Fahrenheit.convert_from_celsius(self.temp)
+    }
+
+    pub fn to_kelvin(&self) -> f32 {
+        TempUnit::Kelvin.convert_from_celsius(self.temp)
+    }
+
+    // Like `==`, but lets the temperatures differ by up to `temp_epsilon`,
+    // so a record survives a round trip through text with rounding. The
+    // humidities, if both are set, get the same tolerance.
+    pub fn approx_eq(&self, other: &Climate, temp_epsilon: f32) -> bool {
+        let close = |a: f32, b: f32| (a - b).abs() <= temp_epsilon;
+        self.city == other.city
+            && self.year == other.year
+            && close(self.temp, other.temp)
+            && match (self.humidity, other.humidity) {
+                (Some(a), Some(b)) => close(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            }
+    }
+
+    // Compares the city with `other`, ignoring case.
+    pub fn city_eq_ignore_case(&self, other: &str) -> bool {
+        self.city
+            .chars()
+            .flat_map(char::to_lowercase)
+            .eq(other.chars().flat_map(char::to_lowercase))
+    }
+
+    // Title-cases the city: the first letter of every word is uppercased
+    // and the rest lowercased. Words are split on whitespace and joined
+    // with single spaces, so leading, trailing and repeated whitespace is
+    // dropped.
+    pub fn normalize_city(&mut self) {
+        self.city = normalized_city(&self.city);
+    }
+
+    // Whether both records observe the same city in the same year, with
+    // cities compared after `normalize_city`. The temperatures and
+    // humidities don't matter.
+    pub fn same_observation(&self, other: &Climate) -> bool {
+        self.year == other.year && normalized_city(&self.city) == normalized_city(&other.city)
+    }
+
+    // The city and year that identify the observation.
+    pub fn key(&self) -> ClimateKey {
+        ClimateKey {
+            city: self.city.clone(),
+            year: self.year,
+        }
+    }
+
+    // The record as a single CSV line, without a line terminator.
+    pub fn to_csv_line(&self) -> String {
+        self.to_string()
+    }
+
+    // The record as a row of fixed-width columns, without a line
+    // terminator: the city left-aligned in 20 characters (cut short if it
+    // is longer), the year right-aligned in 4 and the temperature to one
+    // decimal right-aligned in 6. This is the default `FixedWidthSpec`
+    // layout, under `climate_table_header`. The humidity is left out.
+    pub fn to_table_row(&self) -> String {
+        let city: String = self.city.chars().take(20).collect();
+        format!("{:<20}{:>4}{:>6.1}", city, self.year, self.temp)
+    }
+
+    // The record as a single-line JSON object, e.g.
+    // `{"city":"Oslo","year":2020,"temp":3.4}`. See `to_json_array`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        push_json_object(&mut json, self);
+        json
+    }
+
+    // Parses a JSON object with `city`, `year`, `temp` and optionally
+    // `humidity` keys; other keys are ignored. An absent key, or one whose
+    // value is `null`, is reported as `MissingField`. A value of the wrong
+    // JSON type (such as a year given as a string), a repeated key or text
+    // that isn't JSON is `InvalidJson`. The values are then checked as in
+    // `new`.
+    pub fn from_json(s: &str) -> Result<Climate, ParseClimateError> {
+        const KEYS: [&str; 4] = ["city", "year", "temp", "humidity"];
+        let invalid = ParseClimateError::InvalidJson;
+        let members = match JsonReader::parse(s).map_err(invalid)? {
+            JsonValue::Object(members) => members,
+            _ => return Err(invalid("expected an object".to_string())),
+        };
+        let mut fields: [Option<JsonValue>; 4] = Default::default();
+        for (key, value) in members {
+            if let Some(i) = KEYS.iter().position(|k| *k == key) {
+                if fields[i].is_some() {
+                    return Err(invalid(format!("duplicate key {:?}", key)));
+                }
+                fields[i] = Some(value);
+            }
+        }
+        let [city, year, temp, humidity] = fields.map(|f| f.filter(|v| *v != JsonValue::Null));
+        let wrong_type =
+            |key: &str, expected: &str| invalid(format!("{:?} must be {}", key, expected));
+        let city = match city {
+            Some(JsonValue::String(city)) => city,
+            Some(_) => return Err(wrong_type("city", "a string")),
+            None => return Err(ParseClimateError::MissingField("city")),
         };
-        if city == "" {
-            return Err(ParseClimateError::NoCity);
+        let year = match year {
+            // Only an integer in JSON's own notation, so not `2020.0`.
+            Some(JsonValue::Number(n)) => n
+                .parse::<u32>()
+                .map_err(|_| wrong_type("year", "an integer that fits a u32"))?,
+            Some(_) => return Err(wrong_type("year", "a number")),
+            None => return Err(ParseClimateError::MissingField("year")),
+        };
+        let number = |key: &str, value: JsonValue| match value {
+            JsonValue::Number(n) => n.parse::<f32>().map_err(|_| wrong_type(key, "a number")),
+            _ => Err(wrong_type(key, "a number")),
+        };
+        let temp = match temp {
+            Some(temp) => number("temp", temp)?,
+            None => return Err(ParseClimateError::MissingField("temp")),
+        };
+        let climate = Climate::new(city, year, temp)?;
+        match humidity {
+            Some(humidity) => climate.with_humidity(number("humidity", humidity)?),
+            None => Ok(climate),
+        }
+    }
+
+    // Writes the record in the binary layout: the city's length in bytes as
+    // a `u16`, the UTF-8 city, the year as a `u32` and the temperature as an
+    // `f32`, followed by a `0` byte, or a `1` byte and the humidity as an
+    // `f32`. Numbers are little-endian.
+    pub fn write_binary<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let len = u16::try_from(self.city.len()).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "city name longer than 65535 bytes",
+            )
+        })?;
+        w.write_all(&len.to_le_bytes())?;
+        w.write_all(self.city.as_bytes())?;
+        w.write_all(&self.year.to_le_bytes())?;
+        w.write_all(&self.temp.to_le_bytes())?;
+        match self.humidity {
+            Some(humidity) => {
+                w.write_all(&[1])?;
+                w.write_all(&humidity.to_le_bytes())
+            }
+            None => w.write_all(&[0]),
+        }
+    }
+
+    // Reads a record written by `write_binary`. The year isn't range
+    // checked, since records parsed with wider `ParseOptions::years` must
+    // survive a round trip; everything else is validated as in `new`.
+    pub fn read_binary<R: Read>(r: &mut R) -> Result<Climate, BinaryError> {
+        let len = u16::from_le_bytes(read_bytes(r)?);
+        let mut city = vec![0; usize::from(len)];
+        r.read_exact(&mut city)?;
+        let city =
+            String::from_utf8(city).map_err(|e| ParseClimateError::InvalidUtf8(e.utf8_error()))?;
+        let year = u32::from_le_bytes(read_bytes(r)?);
+        let temp = f32::from_le_bytes(read_bytes(r)?);
+        let humidity = match read_bytes(r)? {
+            [0] => None,
+            [1] => Some(f32::from_le_bytes(read_bytes(r)?)),
+            [tag] => return Err(BinaryError::BadTag(tag)),
+        };
+        let climate = Climate::new_with_years(city, year, temp, &(0..=u32::MAX))?;
+        match humidity {
+            Some(humidity) => Ok(climate.with_humidity(humidity)?),
+            None => Ok(climate),
         }
-        let year: u32 = year.parse()?;
-        let temp: f32 = temp.parse()?;
-        Ok(Climate { city, year, temp })
+    }
+
+    pub fn temp_in(&self, unit: TempUnit) -> f32 {
+        unit.convert_from_celsius(self.temp)
     }
 }
 
-// Don't change anything below this line (other than to enable ignored
-// tests).
+// Builds a `Climate` one field at a time, e.g.
+// `Climate::builder().city("Oslo").year_str("2020").temp(3.4).build()`.
+// Each number can be given as a value or as text to parse; setting a field
+// again replaces the earlier value. `build` applies the same checks as
+// `Climate::new`, but reports every problem at once, in field order, with
+// a field that was never set reported as `MissingField`.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ClimateBuilder {
+    city: Option<String>,
+    // Parse errors from the `_str` setters are kept until `build`.
+    year: Option<Result<u32, ParseClimateError>>,
+    temp: Option<Result<f32, ParseClimateError>>,
+    humidity: Option<Result<f32, ParseClimateError>>,
+}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    println!("{:?}", "Hong Kong,1999,25.7".parse::<Climate>()?);
-    println!("{:?}", "".parse::<Climate>()?);
+impl ClimateBuilder {
+    pub fn new() -> Self {
+        ClimateBuilder::default()
+    }
+
+    pub fn city(mut self, city: impl Into<String>) -> Self {
+        self.city = Some(city.into());
+        self
+    }
+
+    pub fn year(mut self, year: u32) -> Self {
+        self.year = Some(Ok(year));
+        self
+    }
+
+    pub fn year_str(mut self, year: &str) -> Self {
+        // The range is checked by `build`, whichever setter was used.
+        self.year = Some(parse_year(year, &(0..=u32::MAX)));
+        self
+    }
+
+    pub fn temp(mut self, temp: f32) -> Self {
+        self.temp = Some(Ok(temp));
+        self
+    }
+
+    // Takes the same text as the temperature field of a record, including
+    // a unit suffix.
+    pub fn temp_str(mut self, temp: &str) -> Self {
+        self.temp = Some(parse_temp(temp));
+        self
+    }
+
+    pub fn humidity(mut self, humidity: f32) -> Self {
+        self.humidity = Some(Ok(humidity));
+        self
+    }
+
+    pub fn humidity_str(mut self, humidity: &str) -> Self {
+        self.humidity = Some(
+            humidity
+                .parse()
+                .map_err(|e| ParseClimateError::parse_humidity(e, humidity)),
+        );
+        self
+    }
+
+    pub fn build(self) -> Result<Climate, Vec<ParseClimateError>> {
+        let years = Climate::MIN_YEAR..=Climate::MAX_YEAR;
+        let city = match self.city {
+            Some(city) => check_city(&city).map(|()| city),
+            None => Err(ParseClimateError::MissingField("city")),
+        };
+        let year = self
+            .year
+            .unwrap_or(Err(ParseClimateError::MissingField("year")))
+            .and_then(|year| check_year(year, &years));
+        let temp = self
+            .temp
+            .unwrap_or(Err(ParseClimateError::MissingField("temp")))
+            .and_then(check_temp);
+        let humidity = self
+            .humidity
+            .map(|humidity| humidity.and_then(check_humidity))
+            .transpose();
+        match (city, year, temp, humidity) {
+            (Ok(city), Ok(year), Ok(temp), Ok(humidity)) => Ok(Climate {
+                city,
+                year,
+                temp,
+                humidity,
+            }),
+            (city, year, temp, humidity) => {
+                Err([city.err(), year.err(), temp.err(), humidity.err()]
+                    .into_iter()
+                    .flatten()
+                    .collect())
+            }
+        }
+    }
+}
+
+// The city name as `Climate::normalize_city` leaves it.
+fn normalized_city(city: &str) -> String {
+    let mut normalized = String::with_capacity(city.len());
+    for word in city.split_whitespace() {
+        if !normalized.is_empty() {
+            normalized.push(' ');
+        }
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            normalized.extend(first.to_uppercase());
+            normalized.extend(chars.flat_map(char::to_lowercase));
+        }
+    }
+    normalized
+}
+
+// 0 K in degrees Celsius.
+const ABSOLUTE_ZERO: f32 = -273.15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TempUnit {
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+impl TempUnit {
+    // The unit written as a one-letter suffix, e.g. the `F` in `77.4F`.
+    // This is the only place that decides which suffixes a temperature may
+    // carry: an upper-case `C` or `F`. Kelvin is only an output unit, so
+    // `300K` is rejected like any other unknown suffix.
+    pub fn from_suffix(suffix: char) -> Option<TempUnit> {
+        match suffix {
+            'C' => Some(TempUnit::Celsius),
+            'F' => Some(TempUnit::Fahrenheit),
+            _ => None,
+        }
+    }
+
+    // Converts `value`, given in this unit, to degrees Celsius.
+    pub fn convert_to_celsius(self, value: f32) -> f32 {
+        match self {
+            TempUnit::Celsius => value,
+            TempUnit::Fahrenheit => (value - 32.0) * 5.0 / 9.0,
+            TempUnit::Kelvin => value + ABSOLUTE_ZERO,
+        }
+    }
+
+    // Converts `celsius` to this unit.
+    pub fn convert_from_celsius(self, celsius: f32) -> f32 {
+        match self {
+            TempUnit::Celsius => celsius,
+            TempUnit::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+            TempUnit::Kelvin => celsius - ABSOLUTE_ZERO,
+        }
+    }
+}
+
+fn check_city(city: &str) -> Result<(), ParseClimateError> {
+    if city.is_empty() {
+        return Err(ParseClimateError::NoCity);
+    }
+    Ok(())
+}
+
+fn check_year(year: u32, years: &RangeInclusive<u32>) -> Result<u32, ParseClimateError> {
+    if !years.contains(&year) {
+        return Err(ParseClimateError::YearOutOfRange {
+            year: i64::from(year),
+            min: *years.start(),
+            max: *years.end(),
+        });
+    }
+    Ok(year)
+}
+
+fn parse_year(field: &str, years: &RangeInclusive<u32>) -> Result<u32, ParseClimateError> {
+    if field.trim().is_empty() {
+        return Err(ParseClimateError::MissingYear);
+    }
+    let year = field
+        .parse()
+        .map_err(|e| ParseClimateError::parse_int(e, field))?;
+    check_year(year, years)
+}
+
+// Checks a temperature in degrees Celsius.
+fn check_temp(temp: f32) -> Result<f32, ParseClimateError> {
+    if !temp.is_finite() {
+        return Err(ParseClimateError::NotFinite);
+    }
+    if temp < ABSOLUTE_ZERO {
+        return Err(ParseClimateError::BelowAbsoluteZero);
+    }
+    Ok(temp)
+}
+
+// Parses a temperature field into degrees Celsius. The number may be
+// followed by one of the unit suffixes that `TempUnit::from_suffix` allows;
+// any other trailing letter is `UnknownUnit`, and without one the number is
+// taken to be Celsius already. Fields the float parser accepts as they are, such as
+// `inf`, are never treated as having a suffix.
+fn parse_temp(field: &str) -> Result<f32, ParseClimateError> {
+    if field.trim().is_empty() {
+        return Err(ParseClimateError::MissingTemp);
+    }
+    let err = match field.parse::<f32>() {
+        Ok(celsius) => return Ok(celsius),
+        Err(e) => e,
+    };
+    let mut chars = field.chars();
+    let suffix = match chars.next_back() {
+        Some(c) if c.is_ascii_alphabetic() => c,
+        _ => return Err(ParseClimateError::parse_float(err, field)),
+    };
+    let value: f32 = match chars.as_str().parse() {
+        Ok(value) => value,
+        Err(_) => return Err(ParseClimateError::parse_float(err, field)),
+    };
+    match TempUnit::from_suffix(suffix) {
+        Some(unit) => Ok(unit.convert_to_celsius(value)),
+        None => Err(ParseClimateError::UnknownUnit(suffix)),
+    }
+}
+
+fn check_humidity(humidity: f32) -> Result<f32, ParseClimateError> {
+    if !(0.0..=100.0).contains(&humidity) {
+        return Err(ParseClimateError::HumidityOutOfRange(humidity));
+    }
+    Ok(humidity)
+}
+
+fn parse_humidity(field: &str) -> Result<f32, ParseClimateError> {
+    let humidity = field
+        .parse()
+        .map_err(|e| ParseClimateError::parse_humidity(e, field))?;
+    check_humidity(humidity)
+}
+
+// Knobs for `Climate::parse_with_options`. The default is what `FromStr`
+// uses: comma-separated fields that must not carry extra whitespace or a
+// trailing delimiter.
+#[derive(Debug, Clone, PartialEq)]
+struct ParseOptions {
+    pub delimiter: char,
+    // Trim surrounding whitespace from every field before validating it.
+    pub trim_fields: bool,
+    // Accept (and ignore) one delimiter at the end of the record.
+    pub allow_trailing_delimiter: bool,
+    // The years a record may have, `Climate::MIN_YEAR..=Climate::MAX_YEAR`
+    // by default. Widen it for historical reconstructions.
+    pub years: RangeInclusive<u32>,
+    // How the temperature and humidity write their decimal separator.
+    pub number_format: NumberFormat,
+    // Reject numbers that parse but aren't written the usual way: a year
+    // with a leading `+` or leading zeros, or a temperature with a leading
+    // `+`. This keeps records that are equal as values equal as text.
+    pub canonical_numbers: bool,
+}
+
+impl ParseOptions {
+    // Options with the given delimiter and number format, which must not
+    // both use commas. Setting the fields directly skips this check until
+    // the options are used to parse.
+    pub fn new(
+        delimiter: char,
+        number_format: NumberFormat,
+    ) -> Result<ParseOptions, ParseClimateError> {
+        check_delimiter(delimiter, number_format)?;
+        Ok(ParseOptions {
+            delimiter,
+            number_format,
+            ..ParseOptions::default()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum NumberFormat {
+    // `25.7`
+    #[default]
+    DecimalPoint,
+    // `25,7`, as in many European exports. Needs a delimiter other than
+    // `,`.
+    DecimalComma,
+}
+
+// The check behind `ParseOptions::canonical_numbers`.
+fn check_canonical(year: &str, temp: &str) -> Result<(), ParseClimateError> {
+    if year.starts_with('+') || (year.len() > 1 && year.starts_with('0')) {
+        return Err(ParseClimateError::NonCanonicalYear(year.to_string()));
+    }
+    if temp.starts_with('+') {
+        return Err(ParseClimateError::NonCanonicalTemp(temp.to_string()));
+    }
+    Ok(())
+}
+
+// Rejects delimiters that can appear inside the year or temp fields.
+fn check_delimiter(delim: char, number_format: NumberFormat) -> Result<(), ParseClimateError> {
+    let decimal_comma = number_format == NumberFormat::DecimalComma && delim == ',';
+    if delim.is_ascii_digit() || delim == '.' || delim == '-' || decimal_comma {
+        return Err(ParseClimateError::BadDelimiter(delim));
+    }
+    Ok(())
+}
+
+// Rewrites a number in `number_format` with a decimal point for the float
+// parser. With a decimal comma, a `.` can only be a thousands separator
+// and is rejected rather than stripped.
+fn normalize_number(
+    field: &str,
+    number_format: NumberFormat,
+) -> Result<Cow<'_, str>, ParseClimateError> {
+    match number_format {
+        NumberFormat::DecimalPoint => Ok(Cow::Borrowed(field)),
+        NumberFormat::DecimalComma if field.contains('.') => {
+            Err(ParseClimateError::ThousandsSeparator('.'))
+        }
+        NumberFormat::DecimalComma => Ok(field.replace(',', ".").into()),
+    }
+}
+
+// Puts the original text back into a `ParseFloat` or `ParseHumidity` error
+// about a field that `normalize_number` rewrote, given `(rewritten, original)`
+// pairs. Since the rewrite is one-to-one, the error's field identifies the
+// pair.
+fn restore_number_field<'a>(
+    error: ParseClimateError,
+    mut fields: impl Iterator<Item = (&'a str, &'a str)>,
+) -> ParseClimateError {
+    let mut original = |field: String| match fields.find(|&(rewritten, _)| rewritten == field) {
+        Some((_, original)) => original.to_string(),
+        None => field,
+    };
+    match error {
+        ParseClimateError::ParseFloat { source, field } => ParseClimateError::ParseFloat {
+            source,
+            field: original(field),
+        },
+        ParseClimateError::ParseHumidity { source, field } => ParseClimateError::ParseHumidity {
+            source,
+            field: original(field),
+        },
+        error => error,
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            delimiter: ',',
+            trim_fields: false,
+            allow_trailing_delimiter: false,
+            years: Climate::MIN_YEAR..=Climate::MAX_YEAR,
+            number_format: NumberFormat::DecimalPoint,
+            canonical_numbers: false,
+        }
+    }
+}
+
+// The columns of each field in a fixed-width record, counted in characters
+// from 0. The default is the legacy feed layout: the city in columns 0-19,
+// the year in 20-23 and the temperature in 24-29.
+#[derive(Debug, Clone, PartialEq)]
+struct FixedWidthSpec {
+    pub city: Range<usize>,
+    pub year: Range<usize>,
+    pub temp: Range<usize>,
+}
+
+impl Default for FixedWidthSpec {
+    fn default() -> Self {
+        FixedWidthSpec {
+            city: 0..20,
+            year: 20..24,
+            temp: 24..30,
+        }
+    }
+}
+
+// Formats a `Climate` as `city,year,temp`, plus `,humidity` if it has one,
+// the same shape that `FromStr` accepts. `f32`'s `Display` prints the
+// shortest string that parses back to the exact same value, so the output
+// round-trips. Cities containing a comma, quote or newline are quoted.
+impl Display for Climate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.city.contains([',', '"', '\n']) {
+            write!(f, "\"{}\"", self.city.replace('"', "\"\""))?;
+        } else {
+            write!(f, "{}", self.city)?;
+        }
+        write!(f, ",{},{}", self.year, self.temp)?;
+        if let Some(humidity) = self.humidity {
+            write!(f, ",{}", humidity)?;
+        }
+        Ok(())
+    }
+}
+
+// Writes `records` to `w` as CSV, one record per line.
+fn write_climates<W: Write>(w: &mut W, records: &[Climate]) -> io::Result<()> {
+    for record in records {
+        writeln!(w, "{}", record)?;
+    }
     Ok(())
 }
+
+// The header line for rows from `Climate::to_table_row`.
+fn climate_table_header() -> String {
+    format!("{:<20}{:>4}{:>6}", "city", "year", "temp")
+}
+
+// Formats `records` as a JSON array of `{"city", "year", "temp"}` objects,
+// with a `"humidity"` key for records that have one, each as written by
+// `Climate::to_json`.
+fn to_json_array(records: &[Climate]) -> String {
+    let mut json = String::from("[");
+    for (i, record) in records.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        push_json_object(&mut json, record);
+    }
+    json.push(']');
+    json
+}
+
+fn push_json_object(json: &mut String, record: &Climate) {
+    json.push_str("{\"city\":");
+    push_json_string(json, &record.city);
+    // `Climate` temperatures and humidities are always finite, and `f32`'s
+    // `Display` never uses exponent notation, so they are valid JSON
+    // numbers as they are.
+    json.push_str(&format!(
+        ",\"year\":{},\"temp\":{}",
+        record.year, record.temp
+    ));
+    if let Some(humidity) = record.humidity {
+        json.push_str(&format!(",\"humidity\":{}", humidity));
+    }
+    json.push('}');
+}
+
+// Reads JSON Lines from `r`: one `Climate::from_json` object per line.
+// A leading BOM and blank lines are skipped, and errors carry their 1-based
+// line number.
+fn read_jsonl<R: BufRead>(r: R) -> impl Iterator<Item = Result<Climate, ClimateReadError>> {
+    r.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let mut line = line?;
+            if i == 0 && line.starts_with(BOM) {
+                line.drain(..BOM.len_utf8());
+            }
+            Ok((i + 1, line))
+        })
+        .filter(|line| !matches!(line, Ok((_, line)) if line.trim().is_empty()))
+        .map(|line: io::Result<(usize, String)>| {
+            let (n, line) = line?;
+            Climate::from_json(&line).map_err(|error| ClimateReadError::Parse { line: n, error })
+        })
+}
+
+// Writes `records` to `w` as JSON Lines, see `Climate::to_json`.
+fn write_jsonl<W: Write>(w: &mut W, records: &[Climate]) -> io::Result<()> {
+    for record in records {
+        writeln!(w, "{}", record.to_json())?;
+    }
+    Ok(())
+}
+
+fn push_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c < ' ' => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+// A value read by `JsonReader`. A number keeps its text, so that each use
+// can check it is the kind of number it needs.
+#[derive(Debug, Clone, PartialEq)]
+enum JsonValue<'a> {
+    Null,
+    Bool(bool),
+    Number(&'a str),
+    String(String),
+    Array(Vec<JsonValue<'a>>),
+    Object(Vec<(String, JsonValue<'a>)>),
+}
+
+// How deeply arrays and objects may nest, so that hostile input can't
+// overflow the stack.
+const JSON_MAX_DEPTH: usize = 64;
+
+// A small recursive-descent reader for a single JSON document, for
+// `Climate::from_json`. Its errors are the messages of `InvalidJson`, with
+// the 1-based column they were found at.
+struct JsonReader<'a> {
+    s: &'a str,
+    pos: usize,
+}
+
+impl<'a> JsonReader<'a> {
+    // Reads `s` as one JSON value, with nothing but whitespace after it.
+    fn parse(s: &'a str) -> Result<JsonValue<'a>, String> {
+        let mut reader = JsonReader { s, pos: 0 };
+        let value = reader.value(0)?;
+        reader.skip_whitespace();
+        if reader.pos < s.len() {
+            return Err(reader.error("trailing characters"));
+        }
+        Ok(value)
+    }
+
+    fn error(&self, what: &str) -> String {
+        let column = self.s[..self.pos].chars().count() + 1;
+        format!("{} at column {}", what, column)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    // Consumes `byte` if it comes next, after any whitespace.
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.peek() == Some(byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if !self.eat(byte) {
+            return Err(self.error(&format!("expected `{}`", byte as char)));
+        }
+        Ok(())
+    }
+
+    fn value(&mut self, depth: usize) -> Result<JsonValue<'a>, String> {
+        self.skip_whitespace();
+        if depth > JSON_MAX_DEPTH {
+            return Err(self.error("nesting too deep"));
+        }
+        match self.peek() {
+            Some(b'{') => self.object(depth + 1).map(JsonValue::Object),
+            Some(b'[') => self.array(depth + 1).map(JsonValue::Array),
+            Some(b'"') => self.string().map(JsonValue::String),
+            Some(b'-' | b'0'..=b'9') => self.number().map(JsonValue::Number),
+            _ => {
+                let literals = [
+                    ("null", JsonValue::Null),
+                    ("true", JsonValue::Bool(true)),
+                    ("false", JsonValue::Bool(false)),
+                ];
+                for (word, value) in literals {
+                    if self.s[self.pos..].starts_with(word) {
+                        self.pos += word.len();
+                        return Ok(value);
+                    }
+                }
+                Err(self.error("expected a value"))
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Result<Vec<(String, JsonValue<'a>)>, String> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        if self.eat(b'}') {
+            return Ok(members);
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key"));
+            }
+            let key = self.string()?;
+            self.expect(b':')?;
+            members.push((key, self.value(depth)?));
+            if self.eat(b'}') {
+                return Ok(members);
+            }
+            self.expect(b',')?;
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Result<Vec<JsonValue<'a>>, String> {
+        self.pos += 1;
+        let mut elements = Vec::new();
+        if self.eat(b']') {
+            return Ok(elements);
+        }
+        loop {
+            elements.push(self.value(depth)?);
+            if self.eat(b']') {
+                return Ok(elements);
+            }
+            self.expect(b',')?;
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.pos += 1;
+        let mut out = String::new();
+        loop {
+            let c = match self.s[self.pos..].chars().next() {
+                Some(c) => c,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(out),
+                '\\' => out.push(self.escape()?),
+                c if c < ' ' => return Err(self.error("control character in string")),
+                c => out.push(c),
+            }
+        }
+    }
+
+    // The character escaped by the text after a backslash.
+    fn escape(&mut self) -> Result<char, String> {
+        let c = match self.peek() {
+            Some(c) => c,
+            None => return Err(self.error("unterminated string")),
+        };
+        self.pos += 1;
+        let unescaped = match c {
+            b'"' => '"',
+            b'\\' => '\\',
+            b'/' => '/',
+            b'b' => '\u{8}',
+            b'f' => '\u{c}',
+            b'n' => '\n',
+            b'r' => '\r',
+            b't' => '\t',
+            b'u' => {
+                let mut code = self.hex4()?;
+                // A character outside the BMP is escaped as a UTF-16
+                // surrogate pair.
+                if (0xd800..0xdc00).contains(&code) && self.s[self.pos..].starts_with("\\u") {
+                    self.pos += 2;
+                    let low = self.hex4()?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error("invalid surrogate pair"));
+                    }
+                    code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                }
+                match char::from_u32(code) {
+                    Some(c) => c,
+                    None => return Err(self.error("unpaired surrogate")),
+                }
+            }
+            _ => return Err(self.error("invalid escape")),
+        };
+        Ok(unescaped)
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let code = self
+            .s
+            .get(self.pos..self.pos + 4)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok());
+        match code {
+            Some(code) => {
+                self.pos += 4;
+                Ok(code)
+            }
+            None => Err(self.error("invalid \\u escape")),
+        }
+    }
+
+    // A number in JSON's grammar, which is stricter than Rust's: no leading
+    // `+` or zeros, and digits on both sides of a decimal point.
+    fn number(&mut self) -> Result<&'a str, String> {
+        let bytes = self.s.as_bytes();
+        let digits = |pos: &mut usize| {
+            let start = *pos;
+            while bytes.get(*pos).is_some_and(u8::is_ascii_digit) {
+                *pos += 1;
+            }
+            *pos - start
+        };
+        let start = self.pos;
+        let mut pos = start;
+        if bytes.get(pos) == Some(&b'-') {
+            pos += 1;
+        }
+        let int_start = pos;
+        let int_digits = digits(&mut pos);
+        let mut valid = int_digits == 1 || (int_digits > 1 && bytes[int_start] != b'0');
+        if bytes.get(pos) == Some(&b'.') {
+            pos += 1;
+            valid &= digits(&mut pos) > 0;
+        }
+        if matches!(bytes.get(pos), Some(b'e' | b'E')) {
+            pos += 1;
+            if matches!(bytes.get(pos), Some(b'+' | b'-')) {
+                pos += 1;
+            }
+            valid &= digits(&mut pos) > 0;
+        }
+        if !valid {
+            return Err(self.error("invalid number"));
+        }
+        self.pos = pos;
+        Ok(&self.s[start..pos])
+    }
+}
+
+// The version byte at the start of a binary stream.
+const BINARY_VERSION: u8 = 1;
+
+// The error type for reading the binary format.
+#[derive(Debug)]
+enum BinaryError {
+    Io(io::Error),
+    // The input ended in the middle of a record or stream header.
+    UnexpectedEof,
+    UnsupportedVersion(u8),
+    // The byte saying whether a humidity follows was neither 0 nor 1.
+    BadTag(u8),
+    // The record was read but isn't a valid `Climate`.
+    Invalid(ParseClimateError),
+}
+
+impl From<io::Error> for BinaryError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => BinaryError::UnexpectedEof,
+            _ => BinaryError::Io(e),
+        }
+    }
+}
+
+impl From<ParseClimateError> for BinaryError {
+    fn from(e: ParseClimateError) -> Self {
+        BinaryError::Invalid(e)
+    }
+}
+
+impl Display for BinaryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BinaryError::Io(e) => write!(f, "error reading input: {}", e),
+            BinaryError::UnexpectedEof => write!(f, "unexpected end of input"),
+            BinaryError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            BinaryError::BadTag(tag) => write!(f, "invalid humidity tag {}", tag),
+            BinaryError::Invalid(e) => write!(f, "invalid record: {}", e),
+        }
+    }
+}
+
+impl Error for BinaryError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BinaryError::Io(e) => Some(e),
+            BinaryError::Invalid(e) => Some(e),
+            BinaryError::UnexpectedEof
+            | BinaryError::UnsupportedVersion(_)
+            | BinaryError::BadTag(_) => None,
+        }
+    }
+}
+
+fn read_bytes<R: Read, const N: usize>(r: &mut R) -> Result<[u8; N], BinaryError> {
+    let mut bytes = [0; N];
+    r.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+// Writes `records` as a binary stream: the `BINARY_VERSION` byte, the
+// number of records as a little-endian `u32`, then each record as written
+// by `Climate::write_binary`.
+fn write_binary_stream<W: Write>(w: &mut W, records: &[Climate]) -> io::Result<()> {
+    let count = u32::try_from(records.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "too many records"))?;
+    w.write_all(&[BINARY_VERSION])?;
+    w.write_all(&count.to_le_bytes())?;
+    for record in records {
+        record.write_binary(w)?;
+    }
+    Ok(())
+}
+
+fn read_binary_stream<R: Read>(r: &mut R) -> Result<Vec<Climate>, BinaryError> {
+    match read_bytes(r)? {
+        [BINARY_VERSION] => {}
+        [version] => return Err(BinaryError::UnsupportedVersion(version)),
+    }
+    let count = u32::from_le_bytes(read_bytes(r)?);
+    (0..count).map(|_| Climate::read_binary(r)).collect()
+}
+
+// Splits off the first (city) field of a record, returning it together with
+// an iterator over the remaining fields. The city may be wrapped in double
+// quotes, in which case it can contain the delimiter and `""` stands for a
+// literal quote. Any text between the closing quote and the next delimiter
+// is kept as part of the city, as most CSV readers do.
+fn split_fields(
+    s: &str,
+    delim: char,
+) -> Result<(Cow<'_, str>, Split<'_, char>), ParseClimateError> {
+    let quoted = match s.trim_start().strip_prefix('"') {
+        Some(quoted) => quoted,
+        None => {
+            let mut fields = s.split(delim);
+            let city = fields.next().unwrap_or_default();
+            return Ok((Cow::Borrowed(city), fields));
+        }
+    };
+    let mut city = String::new();
+    let mut chars = quoted.char_indices();
+    let rest = loop {
+        match chars.next() {
+            Some((i, '"')) if quoted[i + 1..].starts_with('"') => {
+                city.push('"');
+                chars.next();
+            }
+            Some((i, '"')) => break &quoted[i + 1..],
+            Some((_, c)) => city.push(c),
+            None => return Err(ParseClimateError::UnterminatedQuote),
+        }
+    };
+    let mut rest = rest.split(delim);
+    city.push_str(rest.next().unwrap_or_default());
+    Ok((Cow::Owned(city), rest))
+}
+
+// Builds a `Climate` from a record that has already been split into
+// `[city, year, temp]`, with the same validation as `FromStr`.
+impl TryFrom<&[&str]> for Climate {
+    type Error = ParseClimateError;
+    fn try_from(fields: &[&str]) -> Result<Self, Self::Error> {
+        match fields {
+            [city, year, temp] => Climate::from_fields(
+                Cow::Borrowed(city),
+                year,
+                temp,
+                None,
+                &(Climate::MIN_YEAR..=Climate::MAX_YEAR),
+            ),
+            [city, year, temp, humidity] => Climate::from_fields(
+                Cow::Borrowed(city),
+                year,
+                temp,
+                Some(humidity),
+                &(Climate::MIN_YEAR..=Climate::MAX_YEAR),
+            ),
+            _ => Err(ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: fields.len(),
+            }),
+        }
+    }
+}
+
+// Builds a `Climate` from unparsed `(city, year, temp)` fields, with the
+// same validation as `FromStr`.
+impl TryFrom<(&str, &str, &str)> for Climate {
+    type Error = ParseClimateError;
+    fn try_from((city, year, temp): (&str, &str, &str)) -> Result<Self, Self::Error> {
+        Climate::try_from(&[city, year, temp][..])
+    }
+}
+
+// Builds a `Climate` from a `(city, year, temp)` tuple, validated by
+// `Climate::new`.
+impl TryFrom<(String, u32, f32)> for Climate {
+    type Error = ParseClimateError;
+    fn try_from((city, year, temp): (String, u32, f32)) -> Result<Self, Self::Error> {
+        Climate::new(city, year, temp)
+    }
+}
+
+// Parser for `Climate`, using the default (strict) `ParseOptions`.
+impl FromStr for Climate {
+    type Err = ParseClimateError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Climate::parse_with_options(s, &ParseOptions::default())
+    }
+}
+
+// A record that may lack a temperature reading, see
+// `Climate::parse_allow_missing`.
+#[derive(Debug, Clone, PartialEq)]
+struct ClimateMaybe {
+    pub city: String,
+    pub year: u32,
+    pub temp: Option<f32>,
+    pub humidity: Option<f32>,
+}
+
+// A `Climate` whose city borrows from the parsed text where it can. Only a
+// quoted city needs unescaping and is owned.
+#[derive(Debug, Clone, PartialEq)]
+struct ClimateRef<'a> {
+    pub city: Cow<'a, str>,
+    pub year: u32,
+    pub temp: f32,
+    pub humidity: Option<f32>,
+}
+
+impl<'a> ClimateRef<'a> {
+    // Parses `line` like `FromStr`, borrowing the city from `line` unless
+    // it's quoted.
+    pub fn parse(line: &'a str) -> Result<ClimateRef<'a>, ParseClimateError> {
+        ClimateParser::new().parse_borrowed(line)
+    }
+
+    // Validates and converts the already-split fields of a record, without
+    // copying the city.
+    fn from_fields(
+        city: Cow<'a, str>,
+        year: &str,
+        temp: &str,
+        humidity: Option<&str>,
+        years: &RangeInclusive<u32>,
+    ) -> Result<ClimateRef<'a>, ParseClimateError> {
+        check_city(&city)?;
+        let year = parse_year(year, years)?;
+        let temp = check_temp(parse_temp(temp)?)?;
+        let humidity = humidity.map(parse_humidity).transpose()?;
+        Ok(ClimateRef {
+            city,
+            year,
+            temp,
+            humidity,
+        })
+    }
+
+    pub fn to_owned(&self) -> Climate {
+        self.clone().into_owned()
+    }
+
+    pub fn into_owned(self) -> Climate {
+        Climate {
+            city: self.city.into_owned(),
+            year: self.year,
+            temp: self.temp,
+            humidity: self.humidity,
+        }
+    }
+}
+
+// Parses records without allocating a new `String` for every city: either
+// into an existing `Climate`, reusing its city's capacity, or into a
+// `ClimateRef` that borrows from the input. Both accept exactly what
+// `Climate::parse_with_options` accepts.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ClimateParser {
+    options: ParseOptions,
+}
+
+impl ClimateParser {
+    pub fn new() -> Self {
+        ClimateParser::default()
+    }
+
+    pub fn with_options(options: ParseOptions) -> Self {
+        ClimateParser { options }
+    }
+
+    pub fn parse_borrowed<'a>(&self, line: &'a str) -> Result<ClimateRef<'a>, ParseClimateError> {
+        let options = &self.options;
+        let (city, year, temp, humidity) = Climate::split_record(line, options)?;
+        if options.canonical_numbers {
+            check_canonical(year, temp)?;
+        }
+        let (raw_temp, raw_humidity) = (temp, humidity);
+        let temp = normalize_number(temp, options.number_format)?;
+        let humidity = humidity
+            .map(|h| normalize_number(h, options.number_format))
+            .transpose()?;
+        ClimateRef::from_fields(city, year, &temp, humidity.as_deref(), &options.years).map_err(
+            |e| {
+                let rewritten = humidity.as_deref().zip(raw_humidity);
+                restore_number_field(e, [(&*temp, raw_temp)].into_iter().chain(rewritten))
+            },
+        )
+    }
+
+    // Overwrites `out` with the record in `line`. On error `out` is left
+    // unchanged.
+    pub fn parse_into(&self, line: &str, out: &mut Climate) -> Result<(), ParseClimateError> {
+        let parsed = self.parse_borrowed(line)?;
+        out.city.clear();
+        out.city.push_str(&parsed.city);
+        out.year = parsed.year;
+        out.temp = parsed.temp;
+        out.humidity = parsed.humidity;
+        Ok(())
+    }
+}
+
+// A `Climate` whose city name is shared with every other record for the
+// same city, see `CityInterner`.
+#[derive(Debug, Clone, PartialEq)]
+struct InternedClimate {
+    pub city: Arc<str>,
+    pub year: u32,
+    pub temp: f32,
+    pub humidity: Option<f32>,
+}
+
+impl From<Climate> for InternedClimate {
+    fn from(climate: Climate) -> Self {
+        InternedClimate {
+            city: climate.city.into(),
+            year: climate.year,
+            temp: climate.temp,
+            humidity: climate.humidity,
+        }
+    }
+}
+
+impl From<InternedClimate> for Climate {
+    fn from(climate: InternedClimate) -> Self {
+        Climate {
+            city: climate.city.to_string(),
+            year: climate.year,
+            temp: climate.temp,
+            humidity: climate.humidity,
+        }
+    }
+}
+
+// Deduplicates city names while bulk parsing, so that a large file with few
+// distinct cities only stores each name once. Looking up a name that has
+// already been seen doesn't allocate.
+#[derive(Debug, Clone, Default)]
+struct CityInterner {
+    cities: HashSet<Arc<str>>,
+}
+
+impl CityInterner {
+    pub fn new() -> Self {
+        CityInterner::default()
+    }
+
+    pub fn intern(&mut self, city: &str) -> Arc<str> {
+        if let Some(city) = self.cities.get(city) {
+            return Arc::clone(city);
+        }
+        let city: Arc<str> = city.into();
+        self.cities.insert(Arc::clone(&city));
+        city
+    }
+
+    // The number of distinct cities seen so far.
+    pub fn len(&self) -> usize {
+        self.cities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cities.is_empty()
+    }
+
+    // Parses `line` like `FromStr`, interning the city.
+    pub fn parse_interned(&mut self, line: &str) -> Result<InternedClimate, ParseClimateError> {
+        let parsed = ClimateRef::parse(line)?;
+        Ok(InternedClimate {
+            city: self.intern(&parsed.city),
+            year: parsed.year,
+            temp: parsed.temp,
+            humidity: parsed.humidity,
+        })
+    }
+}
+
+// A `ParseClimateError` together with the place in a multi-line input where
+// it happened. `line` is 1-based, `column` is the 0-based character offset
+// of the start of the offending field.
+#[derive(Debug, PartialEq)]
+struct PositionedError {
+    line: usize,
+    column: usize,
+    kind: ParseClimateError,
+}
+
+impl Display for PositionedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.kind
+        )
+    }
+}
+
+impl Error for PositionedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.kind)
+    }
+}
+
+// A `ParseClimateError` together with the byte range, `start..end`, of the
+// field of the record that caused it. Errors in the structure of the
+// record as a whole have no span.
+#[derive(Debug, PartialEq)]
+struct SpannedError {
+    error: ParseClimateError,
+    span: Option<(usize, usize)>,
+}
+
+impl Display for SpannedError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.span {
+            Some((start, end)) => write!(f, "bytes {}..{}: {}", start, end, self.error),
+            None => write!(f, "{}", self.error),
+        }
+    }
+}
+
+impl Error for SpannedError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+// Returns the character offset of the field in `line` that caused `err`.
+// Errors that concern the record as a whole point at the start of the line.
+fn error_column(line: &str, err: &ParseClimateError) -> usize {
+    use ParseClimateError::*;
+    let field = match err {
+        Empty
+        | BadLen { .. }
+        | NoCity
+        | BadDelimiter(_)
+        | UnterminatedQuote
+        | UnknownColumn(_)
+        | MissingColumn(_)
+        | BadHeader(_)
+        | InvalidUtf8(_)
+        | MissingField(_)
+        | InvalidJson(_) => 0,
+        ParseInt { .. } | YearOutOfRange { .. } | MissingYear | NonCanonicalYear(_) => 1,
+        ParseFloat { .. }
+        | NotFinite
+        | UnknownUnit(_)
+        | BelowAbsoluteZero
+        | MissingTemp
+        | ThousandsSeparator(_)
+        | TempOutOfRange(_)
+        | NonCanonicalTemp(_) => 2,
+        ParseHumidity { .. } | HumidityOutOfRange(_) => 3,
+    };
+    if field == 0 {
+        return 0;
+    }
+    // `split_fields` knows where a quoted city ends, and the fields it
+    // returns are slices of `line`, so their offsets follow from their
+    // addresses.
+    let start = match split_fields(line, ',') {
+        Ok((_, mut rest)) => rest
+            .nth(field - 1)
+            .map_or(0, |f| f.as_ptr() as usize - line.as_ptr() as usize),
+        Err(_) => 0,
+    };
+    line[..start].chars().count()
+}
+
+// Parses every line of `input` as a `Climate`, stopping at the first line
+// that fails. Both `\n` and `\r\n` line endings are accepted, and empty
+// lines at the end of the input are skipped, as is a leading BOM. Unlike
+// the other multi-record parsers this one is strict: blank lines and `#`
+// comments before the end are errors.
+fn parse_climate_lines(input: &str) -> Result<Vec<Climate>, PositionedError> {
+    file_lines(input.trim_end_matches(['\r', '\n']))
+        .map(|(n, line)| {
+            line.parse().map_err(|kind| PositionedError {
+                line: n,
+                column: error_column(line, &kind),
+                kind,
+            })
+        })
+        .collect()
+}
+
+// A parse error tagged with the 1-based number of the line it came from.
+type LineError = (usize, ParseClimateError);
+
+// Which lines of a multi-record input hold no record and are skipped.
+// Skipped lines still count towards the line numbers of later errors.
+#[derive(Debug, Clone, PartialEq)]
+struct LineOptions {
+    // Skip empty and whitespace-only lines.
+    pub skip_blank_lines: bool,
+    // Skip lines starting with this prefix, after optional leading
+    // whitespace. `#` by default.
+    pub comment_prefix: Option<String>,
+}
+
+impl Default for LineOptions {
+    fn default() -> Self {
+        LineOptions {
+            skip_blank_lines: true,
+            comment_prefix: Some("#".to_string()),
+        }
+    }
+}
+
+impl LineOptions {
+    pub fn skips(&self, line: &str) -> bool {
+        let line = line.trim_start();
+        (self.skip_blank_lines && line.is_empty())
+            || self
+                .comment_prefix
+                .as_deref()
+                .is_some_and(|prefix| line.starts_with(prefix))
+    }
+}
+
+// The byte order mark that Windows tools put at the start of UTF-8 files.
+const BOM: char = '\u{feff}';
+
+// Strips a leading byte order mark, as found at the start of a file. An
+// input that is only a BOM is then empty input, and the parsers return no
+// records for it, just as for an empty string.
+fn strip_bom(s: &str) -> &str {
+    s.strip_prefix(BOM).unwrap_or(s)
+}
+
+// Splits a whole file into lines together with their 1-based numbers,
+// tolerating a leading BOM and `\n`, `\r\n` or mixed line endings. Single
+// records parsed with `FromStr` get none of this.
+fn file_lines(input: &str) -> impl Iterator<Item = (usize, &str)> {
+    strip_bom(input)
+        .lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .enumerate()
+        .map(|(i, line)| (i + 1, line))
+}
+
+// Parses every line of `input` as a `Climate`, skipping blank lines and
+// `#` comments. On the first failure, returns the 1-based line number
+// together with the error.
+fn parse_records(input: &str) -> Result<Vec<Climate>, LineError> {
+    parse_records_with(input, &LineOptions::default())
+}
+
+// Checks every record in `input` like `parse_records`, but instead of
+// stopping at the first bad line, returns the error of every bad line with
+// its 1-based line number. An empty result means the whole input is valid.
+fn validate_all(input: &str) -> Vec<LineError> {
+    let options = LineOptions::default();
+    file_lines(input)
+        .filter(|(_, line)| !options.skips(line))
+        .filter_map(|(n, line)| line.parse::<Climate>().err().map(|e| (n, e)))
+        .collect()
+}
+
+// Like `parse_records`, but skipping the lines that `options` says to.
+fn parse_records_with(input: &str, options: &LineOptions) -> Result<Vec<Climate>, LineError> {
+    file_lines(input)
+        .filter(|(_, line)| !options.skips(line))
+        .map(|(n, line)| line.parse().map_err(|e| (n, e)))
+        .collect()
+}
+
+// Lazily parses each line of `input`, skipping a leading BOM, blank lines
+// and `#` comments. Unlike `parse_records`, a bad line doesn't end the iteration;
+// its error is yielded in its place.
+fn records_iter(input: &str) -> impl Iterator<Item = Result<Climate, ParseClimateError>> + '_ {
+    let options = LineOptions::default();
+    file_lines(input)
+        .filter(move |(_, line)| !options.skips(line))
+        .map(|(_, line)| line.parse())
+}
+
+// The column layout of a CSV table, taken from its header row.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ClimateTable {
+    // The index of the city, year and temperature columns, in that order.
+    columns: [usize; 3],
+    humidity: Option<usize>,
+}
+
+impl ClimateTable {
+    const COLUMNS: [&'static str; 4] = ["city", "year", "temp", "humidity"];
+
+    // Parses a table of records. A leading BOM, blank lines and `#`
+    // comments are skipped.
+    // If the first remaining line names any of the columns it is read as a
+    // header, and must name each of `city`, `year` and `temp` exactly once
+    // (in any order, ignoring case), plus at most one `humidity` column;
+    // the following rows are then read in that order. Without a header
+    // every row uses the positional `city,year,temp` order. Line numbers in
+    // errors are 1-based.
+    pub fn parse(s: &str) -> Result<Vec<Climate>, LineError> {
+        let options = LineOptions::default();
+        let mut lines = file_lines(s)
+            .filter(|(_, line)| !options.skips(line))
+            .peekable();
+        let mut table = None;
+        if let Some(&(n, first)) = lines.peek() {
+            table = ClimateTable::from_header(first).map_err(|e| (n, e))?;
+            if table.is_some() {
+                lines.next();
+            }
+        }
+        lines
+            .map(|(n, line)| {
+                match table {
+                    Some(table) => table.parse_row(line),
+                    None => line.parse(),
+                }
+                .map_err(|e| (n, e))
+            })
+            .collect()
+    }
+
+    // Returns `None` if `line` doesn't look like a header.
+    fn from_header(line: &str) -> Result<Option<ClimateTable>, ParseClimateError> {
+        let names: Vec<&str> = line.split(',').map(str::trim).collect();
+        let column_of = |name: &str| {
+            Self::COLUMNS
+                .iter()
+                .position(|c| c.eq_ignore_ascii_case(name))
+        };
+        if names.iter().all(|name| column_of(name).is_none()) {
+            return Ok(None);
+        }
+        let mut columns = [None; 4];
+        for (index, name) in names.iter().enumerate() {
+            match column_of(name) {
+                // A repeated name can't be mapped either.
+                Some(c) if columns[c].is_none() => columns[c] = Some(index),
+                _ => return Err(ParseClimateError::UnknownColumn(name.to_string())),
+            }
+        }
+        let mut found = [0; 3];
+        for (c, column) in columns[..3].iter().enumerate() {
+            found[c] = column.ok_or(ParseClimateError::MissingColumn(Self::COLUMNS[c]))?;
+        }
+        Ok(Some(ClimateTable {
+            columns: found,
+            humidity: columns[3],
+        }))
+    }
+
+    fn parse_row(&self, line: &str) -> Result<Climate, ParseClimateError> {
+        // The canonical order goes through `FromStr` so quoted city names
+        // keep working.
+        let expected = if self.humidity.is_some() { 4 } else { 3 };
+        if self.columns == [0, 1, 2] && matches!(self.humidity, None | Some(3)) {
+            let climate: Climate = line.parse().map_err(|e| match e {
+                ParseClimateError::BadLen { found, .. } => ParseClimateError::BadLen {
+                    expected: expected..=expected,
+                    found,
+                },
+                e => e,
+            })?;
+            // The record parsed, so it has a humidity field exactly when it
+            // has four fields.
+            let found = if climate.humidity.is_some() { 4 } else { 3 };
+            if found != expected {
+                return Err(ParseClimateError::BadLen {
+                    expected: expected..=expected,
+                    found,
+                });
+            }
+            return Ok(climate);
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() != expected {
+            return Err(ParseClimateError::BadLen {
+                expected: expected..=expected,
+                found: fields.len(),
+            });
+        }
+        let [city, year, temp] = self.columns.map(|c| fields[c]);
+        match self.humidity {
+            Some(h) => Climate::try_from(&[city, year, temp, fields[h]][..]),
+            None => Climate::try_from(&[city, year, temp][..]),
+        }
+    }
+}
+
+// Parses a table whose first line (after blank lines and comments) must be
+// a header naming the `city`, `year` and `temp` columns in any order, as
+// accepted by `ClimateTable`. A missing or unusable header is a
+// `BadHeader` error; the following rows are mapped through it.
+fn parse_with_header(input: &str) -> Result<Vec<Climate>, ParseClimateError> {
+    let options = LineOptions::default();
+    let mut lines = file_lines(input)
+        .map(|(_, line)| line)
+        .filter(|line| !options.skips(line));
+    let header = lines.next().unwrap_or_default();
+    let table = match ClimateTable::from_header(header) {
+        Ok(Some(table)) => table,
+        Ok(None) | Err(_) => return Err(ParseClimateError::BadHeader(header.to_string())),
+    };
+    lines.map(|line| table.parse_row(line)).collect()
+}
+
+// The error type yielded by `ClimateReader`.
+#[derive(Debug)]
+enum ClimateReadError {
+    Io(io::Error),
+    // `line` is the 1-based line number of the record that failed.
+    Parse {
+        line: usize,
+        error: ParseClimateError,
+    },
+}
+
+impl From<io::Error> for ClimateReadError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Display for ClimateReadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ClimateReadError::Io(e) => write!(f, "error reading input: {}", e),
+            ClimateReadError::Parse { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl Error for ClimateReadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ClimateReadError::Io(e) => Some(e),
+            ClimateReadError::Parse { error, .. } => Some(error),
+        }
+    }
+}
+
+// Reads `Climate` records one line at a time from any `BufRead`, so large
+// inputs never have to be held in memory at once. A single line buffer is
+// reused for every record.
+struct ClimateReader<R> {
+    reader: R,
+    buf: String,
+    line: usize,
+    lines: LineOptions,
+}
+
+impl<R: BufRead> ClimateReader<R> {
+    // Blank (or whitespace-only) lines and `#` comments are skipped by
+    // default.
+    pub fn new(reader: R) -> Self {
+        ClimateReader {
+            reader,
+            buf: String::new(),
+            line: 0,
+            lines: LineOptions::default(),
+        }
+    }
+
+    // When disabled, blank lines are parsed like any other and therefore
+    // yield an error.
+    pub fn skip_blank_lines(mut self, skip: bool) -> Self {
+        self.lines.skip_blank_lines = skip;
+        self
+    }
+
+    // Sets the prefix of comment lines, or turns comments off with `None`.
+    pub fn comment_prefix(mut self, prefix: Option<&str>) -> Self {
+        self.lines.comment_prefix = prefix.map(str::to_string);
+        self
+    }
+}
+
+impl<R: BufRead> Iterator for ClimateReader<R> {
+    type Item = Result<Climate, ClimateReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) => self.line += 1,
+                Err(e) => return Some(Err(e.into())),
+            }
+            let line = self.buf.strip_suffix('\n').unwrap_or(&self.buf);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let line = if self.line == 1 {
+                strip_bom(line)
+            } else {
+                line
+            };
+            if self.lines.skips(line) {
+                continue;
+            }
+            let result = line.parse().map_err(|error| ClimateReadError::Parse {
+                line: self.line,
+                error,
+            });
+            return Some(result);
+        }
+    }
+}
+
+// Decodes records from a byte stream delivered in arbitrary chunks. Bytes
+// are buffered with `push` until a newline completes a record, which
+// `next_record` then parses. A leading BOM, blank lines and `#` comments
+// are skipped, and a record that isn't valid UTF-8 yields `InvalidUtf8`.
+#[derive(Debug, Clone, Default)]
+struct ClimateDecoder {
+    buf: Vec<u8>,
+    // The start of the first record in `buf` that hasn't been returned.
+    pos: usize,
+    // The number of lines taken from `buf` so far.
+    line: usize,
+    lines: LineOptions,
+}
+
+impl ClimateDecoder {
+    pub fn new() -> Self {
+        ClimateDecoder::default()
+    }
+
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        self.buf.extend_from_slice(bytes);
+    }
+
+    // The next complete record, or `None` until more bytes are pushed.
+    pub fn next_record(&mut self) -> Option<Result<Climate, ParseClimateError>> {
+        loop {
+            let len = self.buf[self.pos..].iter().position(|&b| b == b'\n')?;
+            let line = &self.buf[self.pos..self.pos + len];
+            self.pos += len + 1;
+            self.line += 1;
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let line = match std::str::from_utf8(line) {
+                Ok(line) if self.line == 1 => strip_bom(line),
+                Ok(line) => line,
+                Err(e) => return Some(Err(ParseClimateError::InvalidUtf8(e))),
+            };
+            if !self.lines.skips(line) {
+                return Some(line.parse());
+            }
+        }
+    }
+
+    // Ends the input: a final record without a trailing newline becomes
+    // available from `next_record`.
+    pub fn finish(&mut self) {
+        if self.buf.len() > self.pos && !self.buf.ends_with(b"\n") {
+            self.buf.push(b'\n');
+        }
+    }
+}
+
+// The mean temperature of `records`, or `None` if there are none. The sum
+// is accumulated in `f64` so that long inputs don't lose precision.
+fn average_temp(records: &[Climate]) -> Option<f32> {
+    if records.is_empty() {
+        return None;
+    }
+    let sum: f64 = records.iter().map(|r| f64::from(r.temp)).sum();
+    Some((sum / records.len() as f64) as f32)
+}
+
+// The median temperature of `records`, or `None` if there are none. With
+// an even number of records it is the mean of the two middle ones.
+fn median_temp(records: &[Climate]) -> Option<f32> {
+    if records.is_empty() {
+        return None;
+    }
+    let mut temps: Vec<f32> = records.iter().map(|r| r.temp).collect();
+    temps.sort_by(f32::total_cmp);
+    let mid = temps.len() / 2;
+    if temps.len() % 2 == 1 {
+        Some(temps[mid])
+    } else {
+        Some((temps[mid - 1] + temps[mid]) / 2.0)
+    }
+}
+
+// Counts the records in each temperature band of `bucket_width` degrees.
+// Bucket `k` holds the temperatures in `k * bucket_width..(k + 1) *
+// bucket_width`, so negative temperatures get negative keys.
+//
+// Panics if `bucket_width` isn't a positive finite number.
+fn temp_histogram(records: &[Climate], bucket_width: f32) -> BTreeMap<i32, usize> {
+    assert!(
+        bucket_width.is_finite() && bucket_width > 0.0,
+        "bucket width must be positive, got {}",
+        bucket_width
+    );
+    let mut histogram = BTreeMap::new();
+    for record in records {
+        let bucket = (record.temp / bucket_width).floor() as i32;
+        *histogram.entry(bucket).or_default() += 1;
+    }
+    histogram
+}
+
+// The mean temperature of each year in `records`, across all cities.
+fn avg_temp_by_year(records: &[Climate]) -> BTreeMap<u32, f32> {
+    let mut sums: BTreeMap<u32, (f64, usize)> = BTreeMap::new();
+    for record in records {
+        let (sum, count) = sums.entry(record.year).or_default();
+        *sum += f64::from(record.temp);
+        *count += 1;
+    }
+    sums.into_iter()
+        .map(|(year, (sum, count))| (year, (sum / count as f64) as f32))
+        .collect()
+}
+
+// The temperature in `year` from a series of yearly means, interpolated
+// linearly if there is no mean for it, see `ClimateDataset::interpolate`.
+fn interpolate_year(by_year: &BTreeMap<u32, f32>, year: u32) -> Option<f32> {
+    if let Some(&temp) = by_year.get(&year) {
+        return Some(temp);
+    }
+    let (&before, &low) = by_year.range(..year).next_back()?;
+    let (&after, &high) = by_year.range(year..).next()?;
+    let fraction = f64::from(year - before) / f64::from(after - before);
+    Some((f64::from(low) + (f64::from(high) - f64::from(low)) * fraction) as f32)
+}
+
+// The record with the highest temperature. When several share it, the one
+// with the earliest year wins, and after that the first one in `records`.
+fn warmest(records: &[Climate]) -> Option<&Climate> {
+    records
+        .iter()
+        .min_by(|a, b| b.temp.total_cmp(&a.temp).then(a.year.cmp(&b.year)))
+}
+
+// The record with the lowest temperature, with the same tie-break as
+// `warmest`.
+fn coldest(records: &[Climate]) -> Option<&Climate> {
+    records
+        .iter()
+        .min_by(|a, b| a.temp.total_cmp(&b.temp).then(a.year.cmp(&b.year)))
+}
+
+// Running summary statistics over a collection of records. Every accessor
+// returns `None` until at least one record has been pushed.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ClimateStats {
+    count: usize,
+    // Accumulated in `f64` so that large datasets don't lose precision.
+    sum: f64,
+    min_temp: Option<f32>,
+    max_temp: Option<f32>,
+    min_year: Option<u32>,
+    max_year: Option<u32>,
+}
+
+impl ClimateStats {
+    pub fn new() -> Self {
+        ClimateStats::default()
+    }
+
+    pub fn push(&mut self, record: &Climate) {
+        self.count += 1;
+        self.sum += f64::from(record.temp);
+        self.min_temp = Some(self.min_temp.map_or(record.temp, |t| t.min(record.temp)));
+        self.max_temp = Some(self.max_temp.map_or(record.temp, |t| t.max(record.temp)));
+        self.min_year = Some(self.min_year.map_or(record.year, |y| y.min(record.year)));
+        self.max_year = Some(self.max_year.map_or(record.year, |y| y.max(record.year)));
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    pub fn min_temp(&self) -> Option<f32> {
+        self.min_temp
+    }
+
+    pub fn max_temp(&self) -> Option<f32> {
+        self.max_temp
+    }
+
+    pub fn mean_temp(&self) -> Option<f32> {
+        if self.count == 0 {
+            return None;
+        }
+        Some((self.sum / self.count as f64) as f32)
+    }
+
+    // The earliest and latest year seen.
+    pub fn year_span(&self) -> Option<RangeInclusive<u32>> {
+        Some(self.min_year?..=self.max_year?)
+    }
+}
+
+impl FromIterator<Climate> for ClimateStats {
+    fn from_iter<I: IntoIterator<Item = Climate>>(iter: I) -> Self {
+        let mut stats = ClimateStats::new();
+        for record in iter {
+            stats.push(&record);
+        }
+        stats
+    }
+}
+
+// Borrowed records can be summarized without cloning them.
+impl<'a> FromIterator<&'a Climate> for ClimateStats {
+    fn from_iter<I: IntoIterator<Item = &'a Climate>>(iter: I) -> Self {
+        let mut stats = ClimateStats::new();
+        stats.extend(iter);
+        stats
+    }
+}
+
+impl<'a> Extend<&'a Climate> for ClimateStats {
+    fn extend<I: IntoIterator<Item = &'a Climate>>(&mut self, iter: I) {
+        for record in iter {
+            self.push(record);
+        }
+    }
+}
+
+// A one-call overview of a non-empty collection of records.
+#[derive(Debug, Clone, PartialEq)]
+struct ClimateSummary {
+    pub count: usize,
+    pub years: RangeInclusive<u32>,
+    pub min_temp: f32,
+    pub avg_temp: f32,
+    pub max_temp: f32,
+}
+
+impl ClimateSummary {
+    // `None` if `records` is empty.
+    pub fn from_records(records: &[Climate]) -> Option<ClimateSummary> {
+        let stats: ClimateStats = records.iter().collect();
+        Some(ClimateSummary {
+            count: stats.count(),
+            years: stats.year_span()?,
+            min_temp: stats.min_temp()?,
+            avg_temp: stats.mean_temp()?,
+            max_temp: stats.max_temp()?,
+        })
+    }
+}
+
+// For example "12 records, 1999–2021, temp min 3.1 / avg 14.7 / max 28.9".
+impl Display for ClimateSummary {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let noun = if self.count == 1 { "record" } else { "records" };
+        write!(
+            f,
+            "{} {}, {}\u{2013}{}, temp min {:.1} / avg {:.1} / max {:.1}",
+            self.count,
+            noun,
+            self.years.start(),
+            self.years.end(),
+            self.min_temp,
+            self.avg_temp,
+            self.max_temp
+        )
+    }
+}
+
+// The sorted temperatures of a collection of records, for the order
+// statistics that `ClimateStats` can't keep up incrementally. Every
+// accessor returns `None` when there are no temperatures.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ClimateDistribution {
+    // Sorted with `f32::total_cmp`.
+    temps: Vec<f32>,
+}
+
+impl ClimateDistribution {
+    pub fn len(&self) -> usize {
+        self.temps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.temps.is_empty()
+    }
+
+    pub fn median(&self) -> Option<f32> {
+        self.percentile(0.5)
+    }
+
+    // The temperature below which a fraction `p` of the temperatures lie,
+    // interpolating linearly between neighbouring ranks: `percentile(0.0)`
+    // is the lowest temperature and `percentile(1.0)` the highest.
+    //
+    // Panics if `p` is outside `0.0..=1.0`, since that is always a bug in
+    // the caller.
+    pub fn percentile(&self, p: f64) -> Option<f32> {
+        assert!(
+            (0.0..=1.0).contains(&p),
+            "percentile must be within 0.0..=1.0, got {}",
+            p
+        );
+        let last = self.temps.len().checked_sub(1)?;
+        let rank = p * last as f64;
+        let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+        let (low, high) = (f64::from(self.temps[below]), f64::from(self.temps[above]));
+        Some((low + (high - low) * (rank - below as f64)) as f32)
+    }
+
+    // The spread of the middle half of the temperatures.
+    pub fn interquartile_range(&self) -> Option<f32> {
+        Some(self.percentile(0.75)? - self.percentile(0.25)?)
+    }
+}
+
+impl FromIterator<Climate> for ClimateDistribution {
+    fn from_iter<I: IntoIterator<Item = Climate>>(iter: I) -> Self {
+        let mut temps: Vec<f32> = iter.into_iter().map(|r| r.temp).collect();
+        temps.sort_by(f32::total_cmp);
+        ClimateDistribution { temps }
+    }
+}
+
+// A collection of records together with running aggregates, kept up to
+// date as records are pushed so the accessors don't rescan the records.
+// `warmest` and `coldest` break ties like the free functions of the same
+// name.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ClimateSet {
+    records: Vec<Climate>,
+    stats: ClimateStats,
+    // Indices into `records`.
+    warmest: Option<usize>,
+    coldest: Option<usize>,
+}
+
+impl ClimateSet {
+    pub fn new() -> Self {
+        ClimateSet::default()
+    }
+
+    pub fn push(&mut self, record: Climate) {
+        let index = self.records.len();
+        // A record replaces the current extreme if it beats it outright, or
+        // ties with it in an earlier year. The first record is both.
+        let warmer = match self.warmest() {
+            Some(current) => {
+                let order = record.temp.total_cmp(&current.temp);
+                order.then(current.year.cmp(&record.year)) == Ordering::Greater
+            }
+            None => true,
+        };
+        let colder = match self.coldest() {
+            Some(current) => {
+                let order = current.temp.total_cmp(&record.temp);
+                order.then(current.year.cmp(&record.year)) == Ordering::Greater
+            }
+            None => true,
+        };
+        if warmer {
+            self.warmest = Some(index);
+        }
+        if colder {
+            self.coldest = Some(index);
+        }
+        self.stats.push(&record);
+        self.records.push(record);
+    }
+
+    pub fn count(&self) -> usize {
+        self.stats.count()
+    }
+
+    pub fn avg_temp(&self) -> Option<f32> {
+        self.stats.mean_temp()
+    }
+
+    pub fn warmest(&self) -> Option<&Climate> {
+        self.warmest.map(|i| &self.records[i])
+    }
+
+    pub fn coldest(&self) -> Option<&Climate> {
+        self.coldest.map(|i| &self.records[i])
+    }
+}
+
+impl FromIterator<Climate> for ClimateSet {
+    fn from_iter<I: IntoIterator<Item = Climate>>(iter: I) -> Self {
+        let mut set = ClimateSet::new();
+        for record in iter {
+            set.push(record);
+        }
+        set
+    }
+}
+
+// Identifies an observation by city and year, so records can be used as
+// `HashSet`/`HashMap` keys despite the `f32` temperature.
+#[derive(Debug, Clone)]
+struct ClimateKey {
+    pub city: String,
+    pub year: u32,
+}
+
+// A borrowed view of a `ClimateKey`. Maps keyed by `ClimateKey` can be
+// queried with a `(&str, u32)` through `dyn ClimateKeyRef` without
+// allocating a `String`, e.g. `map.get::<dyn ClimateKeyRef>(&("Oslo", 2020))`.
+trait ClimateKeyRef {
+    fn city(&self) -> &str;
+    fn year(&self) -> u32;
+}
+
+impl ClimateKeyRef for ClimateKey {
+    fn city(&self) -> &str {
+        &self.city
+    }
+
+    fn year(&self) -> u32 {
+        self.year
+    }
+}
+
+impl ClimateKeyRef for (&str, u32) {
+    fn city(&self) -> &str {
+        self.0
+    }
+
+    fn year(&self) -> u32 {
+        self.1
+    }
+}
+
+impl<'a> Borrow<dyn ClimateKeyRef + 'a> for ClimateKey {
+    fn borrow(&self) -> &(dyn ClimateKeyRef + 'a) {
+        self
+    }
+}
+
+// `Borrow` requires the owned and borrowed forms to hash and compare the
+// same way, so `ClimateKey` forwards all of these to `dyn ClimateKeyRef`.
+impl Hash for dyn ClimateKeyRef + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.city().hash(state);
+        self.year().hash(state);
+    }
+}
+
+impl PartialEq for dyn ClimateKeyRef + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.city() == other.city() && self.year() == other.year()
+    }
+}
+
+impl Eq for dyn ClimateKeyRef + '_ {}
+
+impl Ord for dyn ClimateKeyRef + '_ {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.city()
+            .cmp(other.city())
+            .then_with(|| self.year().cmp(&other.year()))
+    }
+}
+
+impl PartialOrd for dyn ClimateKeyRef + '_ {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Hash for ClimateKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self as &dyn ClimateKeyRef).hash(state);
+    }
+}
+
+impl PartialEq for ClimateKey {
+    fn eq(&self, other: &Self) -> bool {
+        (self as &dyn ClimateKeyRef) == (other as &dyn ClimateKeyRef)
+    }
+}
+
+impl Eq for ClimateKey {}
+
+impl Ord for ClimateKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self as &dyn ClimateKeyRef).cmp(other as &dyn ClimateKeyRef)
+    }
+}
+
+impl PartialOrd for ClimateKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Drops every record whose city and year were already seen, keeping the
+// first occurrence and the original order.
+fn dedup_by_key(records: Vec<Climate>) -> Vec<Climate> {
+    let mut seen = HashSet::new();
+    records
+        .into_iter()
+        .filter(|r| {
+            let key: &dyn ClimateKeyRef = &(r.city.as_str(), r.year);
+            !seen.contains(key) && seen.insert(r.key())
+        })
+        .collect()
+}
+
+// The index pairs `(i, j)`, with `i < j`, of records that observe the same
+// city in the same year, with cities compared as in
+// `Climate::same_observation`. They usually mean conflicting entries. The
+// pairs are sorted, and a city-year with three records gives three pairs.
+fn find_duplicates(records: &[Climate]) -> Vec<(usize, usize)> {
+    let mut seen: HashMap<ClimateKey, Vec<usize>> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (j, record) in records.iter().enumerate() {
+        let key = ClimateKey {
+            city: normalized_city(&record.city),
+            year: record.year,
+        };
+        let earlier = seen.entry(key).or_default();
+        duplicates.extend(earlier.iter().map(|&i| (i, j)));
+        earlier.push(j);
+    }
+    duplicates.sort_unstable();
+    duplicates
+}
+
+// A least-squares line through (year, temperature) points:
+// `temp = intercept + slope_per_year * year`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Trend {
+    pub slope_per_year: f64,
+    pub intercept: f64,
+    // The fraction of the variance in temperature that the line explains.
+    // It is 1 when the temperatures don't vary at all, as the line then
+    // fits them exactly.
+    pub r_squared: f64,
+}
+
+impl Trend {
+    // Fits a line through `records` by ordinary least squares. Returns
+    // `None` unless there are records from at least two different years.
+    pub fn fit(records: &[Climate]) -> Option<Trend> {
+        if records.len() < 2 {
+            return None;
+        }
+        let n = records.len() as f64;
+        let mean_x = records.iter().map(|r| f64::from(r.year)).sum::<f64>() / n;
+        let mean_y = records.iter().map(|r| f64::from(r.temp)).sum::<f64>() / n;
+        let (mut sxx, mut sxy, mut syy) = (0.0, 0.0, 0.0);
+        for record in records {
+            let dx = f64::from(record.year) - mean_x;
+            let dy = f64::from(record.temp) - mean_y;
+            sxx += dx * dx;
+            sxy += dx * dy;
+            syy += dy * dy;
+        }
+        if sxx == 0.0 {
+            return None;
+        }
+        let slope_per_year = sxy / sxx;
+        Some(Trend {
+            slope_per_year,
+            intercept: mean_y - slope_per_year * mean_x,
+            r_squared: if syy == 0.0 {
+                1.0
+            } else {
+                sxy * sxy / (sxx * syy)
+            },
+        })
+    }
+
+    pub fn slope_per_decade(&self) -> f64 {
+        self.slope_per_year * 10.0
+    }
+}
+
+// What changed from one dataset to another, see `ClimateDataset::diff`.
+// Each list is sorted by city and then year.
+#[derive(Debug, Clone, PartialEq, Default)]
+struct ClimateDiff {
+    pub only_in_self: Vec<Climate>,
+    pub only_in_other: Vec<Climate>,
+    // The record in self and the record in other.
+    pub changed: Vec<(Climate, Climate)>,
+}
+
+impl ClimateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.changed.is_empty()
+    }
+}
+
+// One line per entry, in city and then year order: "- " for a record only
+// in self, "+ " for one only in other and "~ " for a changed temperature.
+impl Display for ClimateDiff {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut lines: Vec<(&str, u32, String)> = Vec::new();
+        for r in &self.only_in_self {
+            lines.push((&r.city, r.year, format!("- {}", r)));
+        }
+        for r in &self.only_in_other {
+            lines.push((&r.city, r.year, format!("+ {}", r)));
+        }
+        for (old, new) in &self.changed {
+            let line = format!("~ {},{}: {} -> {}", old.city, old.year, old.temp, new.temp);
+            lines.push((&old.city, old.year, line));
+        }
+        // Stable, so a city and year keeps "-" before "+" before "~".
+        lines.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        for (_, _, line) in lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+// How `ClimateDataset::merge` resolves a city and year that both datasets
+// have records for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MergePolicy {
+    // Keep the records of the dataset being merged into.
+    KeepFirst,
+    // Keep the records of the dataset being merged in.
+    KeepLast,
+    // Replace all of them with one record holding their mean temperature,
+    // and the mean of the humidities that are present.
+    Average,
+    // Fail with a `MergeConflict`, unless the records are identical.
+    Error,
+}
+
+// The first city and year, in city and then year order, for which
+// `MergePolicy::Error` found differing records.
+#[derive(Debug, Clone, PartialEq)]
+struct MergeConflict {
+    pub city: String,
+    pub year: u32,
+    pub left: Climate,
+    pub right: Climate,
+}
+
+impl Display for MergeConflict {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "conflicting records for {:?} in {}: {} and {}",
+            self.city, self.year, self.left, self.right
+        )
+    }
+}
+
+impl Error for MergeConflict {}
+
+// The error type for `ClimateDataset::anomalies`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum AnomalyError {
+    // The dataset has no records for the city.
+    UnknownCity(String),
+    // None of the city's records lie within the baseline years.
+    EmptyBaseline(String),
+}
+
+impl Display for AnomalyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AnomalyError::UnknownCity(city) => write!(f, "no records for {:?}", city),
+            AnomalyError::EmptyBaseline(city) => {
+                write!(f, "no records for {:?} in the baseline years", city)
+            }
+        }
+    }
+}
+
+impl Error for AnomalyError {}
+
+// How `ClimateDataset::moving_average` treats years without a record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GapPolicy {
+    // The window covers the nearest years that have records, however far
+    // apart they are.
+    SkipMissing,
+    // The window covers consecutive years; a year whose window includes a
+    // missing year gets no average.
+    Strict,
+}
+
+// Records grouped by city. Cities are kept in sorted order and each city's
+// records are kept sorted by year; records with the same year stay in
+// insertion order.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ClimateDataset {
+    by_city: BTreeMap<String, Vec<Climate>>,
+}
+
+impl ClimateDataset {
+    pub fn new() -> Self {
+        ClimateDataset::default()
+    }
+
+    pub fn insert(&mut self, record: Climate) {
+        let records = self.by_city.entry(record.city.clone()).or_default();
+        let at = records.partition_point(|r| r.year <= record.year);
+        records.insert(at, record);
+    }
+
+    // Adds every record of `other`, resolving each city and year that both
+    // datasets have records for by `policy`. Records for other cities and
+    // years are all kept.
+    pub fn merge(
+        mut self,
+        other: ClimateDataset,
+        policy: MergePolicy,
+    ) -> Result<ClimateDataset, MergeConflict> {
+        for (city, theirs) in other.by_city {
+            let mut theirs = theirs.into_iter().peekable();
+            while let Some(first) = theirs.next() {
+                let year = first.year;
+                let mut right = vec![first];
+                while let Some(record) = theirs.next_if(|r| r.year == year) {
+                    right.push(record);
+                }
+                let left = self.take_year(&city, year);
+                let kept = match policy {
+                    _ if left.is_empty() => right,
+                    MergePolicy::KeepFirst => left,
+                    MergePolicy::KeepLast => right,
+                    MergePolicy::Average => vec![average_records(left.into_iter().chain(right))],
+                    MergePolicy::Error => {
+                        let conflict = left
+                            .iter()
+                            .flat_map(|l| right.iter().map(move |r| (l, r)))
+                            .find(|(l, r)| l != r);
+                        if let Some((left, right)) = conflict {
+                            return Err(MergeConflict {
+                                city,
+                                year,
+                                left: left.clone(),
+                                right: right.clone(),
+                            });
+                        }
+                        left
+                    }
+                };
+                // `kept` is never empty, so the city doesn't lose its
+                // records for good.
+                for record in kept {
+                    self.insert(record);
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    // Removes and returns the records for `city` in `year`.
+    fn take_year(&mut self, city: &str, year: u32) -> Vec<Climate> {
+        let Some(records) = self.by_city.get_mut(city) else {
+            return Vec::new();
+        };
+        let start = records.partition_point(|r| r.year < year);
+        let end = records.partition_point(|r| r.year <= year);
+        records.drain(start..end).collect()
+    }
+
+    // The number of records across all cities.
+    pub fn len(&self) -> usize {
+        self.by_city.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_city.is_empty()
+    }
+
+    pub fn cities(&self) -> Vec<&str> {
+        self.by_city.keys().map(String::as_str).collect()
+    }
+
+    // The records for `city`, sorted by year. Empty for an unknown city.
+    pub fn records_for(&self, city: &str) -> &[Climate] {
+        self.by_city.get(city).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn years_for(&self, city: &str) -> Vec<u32> {
+        self.records_for(city).iter().map(|r| r.year).collect()
+    }
+
+    // The records only in `self`, those only in `other` and those whose
+    // temperatures differ by more than `temp_epsilon`, matched by city and
+    // year. A `temp_epsilon` of 0.0 requires identical temperatures, so
+    // 0.0 and -0.0 differ. Records with the same city and year are matched
+    // up in order, and the unmatched ones count as only in their dataset.
+    //
+    // Panics if `temp_epsilon` is negative or NaN.
+    pub fn diff(&self, other: &ClimateDataset, temp_epsilon: f32) -> ClimateDiff {
+        assert!(
+            temp_epsilon >= 0.0,
+            "temp_epsilon must not be negative or NaN, got {}",
+            temp_epsilon
+        );
+        type Sides<'a> = (Vec<&'a Climate>, Vec<&'a Climate>);
+        let mut by_key: BTreeMap<(&str, u32), Sides> = BTreeMap::new();
+        for r in self.records() {
+            by_key.entry((&r.city, r.year)).or_default().0.push(r);
+        }
+        for r in other.records() {
+            by_key.entry((&r.city, r.year)).or_default().1.push(r);
+        }
+        let differs = |a: f32, b: f32| {
+            if temp_epsilon == 0.0 {
+                a.to_bits() != b.to_bits()
+            } else {
+                (a - b).abs() > temp_epsilon
+            }
+        };
+        let mut diff = ClimateDiff::default();
+        for (left, right) in by_key.into_values() {
+            for (l, r) in left.iter().zip(&right) {
+                if differs(l.temp, r.temp) {
+                    diff.changed.push(((*l).clone(), (*r).clone()));
+                }
+            }
+            let paired = left.len().min(right.len());
+            diff.only_in_self
+                .extend(left[paired..].iter().map(|&r| r.clone()));
+            diff.only_in_other
+                .extend(right[paired..].iter().map(|&r| r.clone()));
+        }
+        diff
+    }
+
+    // Every city with its records, in city order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[Climate])> {
+        self.by_city
+            .iter()
+            .map(|(city, records)| (city.as_str(), records.as_slice()))
+    }
+
+    // The (city, year) pairs that occur more than once, in city and then
+    // year order.
+    pub fn duplicates(&self) -> Vec<(&str, u32)> {
+        let mut duplicates = Vec::new();
+        for (city, records) in self.iter() {
+            for pair in records.windows(2) {
+                let year = pair[1].year;
+                if pair[0].year == year && duplicates.last() != Some(&(city, year)) {
+                    duplicates.push((city, year));
+                }
+            }
+        }
+        duplicates
+    }
+
+    // Every record, ordered by year. Records from the same year are in
+    // city order.
+    pub fn sort_by_year(&self) -> Vec<&Climate> {
+        let mut records: Vec<_> = self.by_city.values().flatten().collect();
+        records.sort_by(|a, b| a.year.cmp(&b.year).then_with(|| a.cmp(b)));
+        records
+    }
+
+    // Every record, coldest first. Equal temperatures fall back to the
+    // `Ord` order.
+    pub fn sort_by_temp(&self) -> Vec<&Climate> {
+        let mut records: Vec<_> = self.by_city.values().flatten().collect();
+        records.sort_by(|a, b| a.temp.total_cmp(&b.temp).then_with(|| a.cmp(b)));
+        records
+    }
+
+    // The filters below yield records in city and then year order, the
+    // order they are stored in.
+    fn records(&self) -> impl Iterator<Item = &Climate> {
+        self.by_city.values().flatten()
+    }
+
+    pub fn filter_years(&self, years: impl RangeBounds<u32>) -> impl Iterator<Item = &Climate> {
+        self.records().filter(move |r| years.contains(&r.year))
+    }
+
+    pub fn filter_temp(&self, temps: impl RangeBounds<f32>) -> impl Iterator<Item = &Climate> {
+        self.records().filter(move |r| temps.contains(&r.temp))
+    }
+
+    // The records whose city is exactly `city`.
+    pub fn filter_city(&self, city: &str) -> impl Iterator<Item = &Climate> {
+        self.records_for(city).iter()
+    }
+
+    // The `n` warmest records, warmest first. Records with the same
+    // temperature are in `Ord` order, that is by city and then year.
+    pub fn top_n_by_temp(&self, n: usize) -> Vec<&Climate> {
+        extreme_n(self.records(), n, true)
+    }
+
+    // The `n` coldest records, coldest first, with the same tie-break as
+    // `top_n_by_temp`.
+    pub fn bottom_n_by_temp(&self, n: usize) -> Vec<&Climate> {
+        extreme_n(self.records(), n, false)
+    }
+
+    pub fn top_n_by_temp_for(&self, city: &str, n: usize) -> Vec<&Climate> {
+        extreme_n(self.filter_city(city), n, true)
+    }
+
+    pub fn bottom_n_by_temp_for(&self, city: &str, n: usize) -> Vec<&Climate> {
+        extreme_n(self.filter_city(city), n, false)
+    }
+
+    // The centered moving average of `city`'s temperatures over `window`
+    // years, with one entry for each year that has records. Records from
+    // the same year are averaged first. A window spans `(window - 1) / 2`
+    // years before and `window / 2` years after its year; where the window
+    // runs past either end of the series, or into a gap under
+    // `GapPolicy::Strict`, the entry is `None`. Returns `None` if `window`
+    // is 0.
+    pub fn moving_average(
+        &self,
+        city: &str,
+        window: usize,
+        gaps: GapPolicy,
+    ) -> Option<Vec<(u32, Option<f32>)>> {
+        if window == 0 {
+            return None;
+        }
+        let series: Vec<(u32, f32)> = avg_temp_by_year(self.records_for(city))
+            .into_iter()
+            .collect();
+        let (before, after) = ((window - 1) / 2, window / 2);
+        let mean = |temps: &[(u32, f32)]| {
+            let sum: f64 = temps.iter().map(|&(_, t)| f64::from(t)).sum();
+            (sum / temps.len() as f64) as f32
+        };
+        let averages = series
+            .iter()
+            .enumerate()
+            .map(|(i, &(year, _))| {
+                let average = i
+                    .checked_sub(before)
+                    .map(|start| start..i + after + 1)
+                    .filter(|span| span.end <= series.len())
+                    .map(|span| &series[span])
+                    .filter(|temps| match gaps {
+                        GapPolicy::SkipMissing => true,
+                        GapPolicy::Strict => {
+                            let (first, last) = (temps[0].0, temps[temps.len() - 1].0);
+                            (last - first) as usize + 1 == window
+                        }
+                    })
+                    .map(mean);
+                (year, average)
+            })
+            .collect();
+        Some(averages)
+    }
+
+    // The difference of each of `city`'s temperatures from its mean
+    // temperature over the `baseline` years, in year order.
+    pub fn anomalies(
+        &self,
+        city: &str,
+        baseline: RangeInclusive<u32>,
+    ) -> Result<Vec<(u32, f32)>, AnomalyError> {
+        let records = self.records_for(city);
+        if records.is_empty() {
+            return Err(AnomalyError::UnknownCity(city.to_string()));
+        }
+        let (sum, count) = records
+            .iter()
+            .filter(|r| baseline.contains(&r.year))
+            .fold((0.0, 0), |(sum, count), r| {
+                (sum + f64::from(r.temp), count + 1)
+            });
+        if count == 0 {
+            return Err(AnomalyError::EmptyBaseline(city.to_string()));
+        }
+        let mean = (sum / count as f64) as f32;
+        Ok(records.iter().map(|r| (r.year, r.temp - mean)).collect())
+    }
+
+    // `anomalies` for every city. Cities without records in the baseline
+    // years are left out.
+    pub fn anomalies_by_city(
+        &self,
+        baseline: RangeInclusive<u32>,
+    ) -> BTreeMap<String, Vec<(u32, f32)>> {
+        self.cities()
+            .into_iter()
+            .filter_map(|city| {
+                let anomalies = self.anomalies(city, baseline.clone()).ok()?;
+                Some((city.to_string(), anomalies))
+            })
+            .collect()
+    }
+
+    // The records whose temperature is more than `threshold_sigma` sample
+    // standard deviations from their city's mean, in city and then year
+    // order. Cities with fewer than 3 records have no outliers.
+    pub fn outliers(&self, threshold_sigma: f64) -> Vec<&Climate> {
+        self.by_city
+            .values()
+            .flat_map(|records| {
+                let flags = outlier_flags(records, threshold_sigma);
+                records.iter().zip(flags).filter(|&(_, outlier)| outlier)
+            })
+            .map(|(record, _)| record)
+            .collect()
+    }
+
+    // Removes the records that `outliers` returns, and with them any city
+    // that is left without records.
+    pub fn retain_inliers(&mut self, threshold_sigma: f64) {
+        for records in self.by_city.values_mut() {
+            let mut flags = outlier_flags(records, threshold_sigma).into_iter();
+            records.retain(|_| !flags.next().unwrap_or(false));
+        }
+        self.by_city.retain(|_, records| !records.is_empty());
+    }
+
+    // `city`'s temperature in `year`: the mean of that year's records if
+    // there are any, or else interpolated linearly between the nearest
+    // years before and after it that have records. `None` for a year
+    // outside the span of the records, which are never extrapolated.
+    pub fn interpolate(&self, city: &str, year: u32) -> Option<f32> {
+        interpolate_year(&avg_temp_by_year(self.records_for(city)), year)
+    }
+
+    // `city`'s records with a record added for every missing year between
+    // the first and the last, in year order. Each comes with whether it was
+    // interpolated; interpolated records have no humidity.
+    pub fn fill_gaps(&self, city: &str) -> Vec<(Climate, bool)> {
+        let records = self.records_for(city);
+        let by_year = avg_temp_by_year(records);
+        let mut filled = Vec::with_capacity(records.len());
+        let mut next_year = None;
+        for record in records {
+            for year in next_year.unwrap_or(record.year)..record.year {
+                // The year lies between two with records, so there is an
+                // interpolated temperature, and it is valid like theirs.
+                if let Some(temp) = interpolate_year(&by_year, year) {
+                    let climate = Climate {
+                        city: record.city.clone(),
+                        year,
+                        temp,
+                        humidity: None,
+                    };
+                    filled.push((climate, true));
+                }
+            }
+            filled.push((record.clone(), false));
+            // There is no year after `u32::MAX` to fill, and any records
+            // after it are from the same year.
+            next_year = record.year.checked_add(1);
+        }
+        filled
+    }
+
+    // The temperature trend of `city`, see `Trend::fit`.
+    pub fn trend_for(&self, city: &str) -> Option<Trend> {
+        Trend::fit(self.records_for(city))
+    }
+
+    pub fn stats_by_city(&self) -> BTreeMap<String, ClimateStats> {
+        self.iter()
+            .map(|(city, records)| (city.to_string(), records.iter().collect()))
+            .collect()
+    }
+
+    // The city with the highest mean temperature, and that mean. Of cities
+    // with the same mean, the first in city order wins.
+    pub fn warmest_city(&self) -> Option<(&str, f32)> {
+        self.extreme_city(|mean, best| mean > best)
+    }
+
+    // The city with the lowest mean temperature, with the same tie-break
+    // as `warmest_city`.
+    pub fn coldest_city(&self) -> Option<(&str, f32)> {
+        self.extreme_city(|mean, best| mean < best)
+    }
+
+    fn extreme_city(&self, beats: impl Fn(f32, f32) -> bool) -> Option<(&str, f32)> {
+        let mut extreme: Option<(&str, f32)> = None;
+        for (city, records) in self.iter() {
+            // A city is only present once it has a record.
+            let Some(mean) = average_temp(records) else {
+                continue;
+            };
+            let beaten = match extreme {
+                Some((_, best)) => beats(mean, best),
+                None => true,
+            };
+            if beaten {
+                extreme = Some((city, mean));
+            }
+        }
+        extreme
+    }
+
+    // One line per city, in city order, with its record count and mean,
+    // lowest and highest temperature:
+    // `Oslo: n=12 mean=4.3 min=-8.1 max=19.2`.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for (city, stats) in self.stats_by_city() {
+            // A city is only present once it has a record.
+            let (Some(mean), Some(min), Some(max)) =
+                (stats.mean_temp(), stats.min_temp(), stats.max_temp())
+            else {
+                continue;
+            };
+            report.push_str(&format!(
+                "{}: n={} mean={:.1} min={:.1} max={:.1}\n",
+                city,
+                stats.count(),
+                mean,
+                min,
+                max
+            ));
+        }
+        report
+    }
+}
+
+// Whether each of `records` is an outlier, see `ClimateDataset::outliers`.
+fn outlier_flags(records: &[Climate], threshold_sigma: f64) -> Vec<bool> {
+    if records.len() < 3 {
+        return vec![false; records.len()];
+    }
+    let n = records.len() as f64;
+    let mean = records.iter().map(|r| f64::from(r.temp)).sum::<f64>() / n;
+    let variance = records
+        .iter()
+        .map(|r| (f64::from(r.temp) - mean).powi(2))
+        .sum::<f64>()
+        / (n - 1.0);
+    let limit = threshold_sigma * variance.sqrt();
+    records
+        .iter()
+        .map(|r| (f64::from(r.temp) - mean).abs() > limit)
+        .collect()
+}
+
+// One record for non-empty `records` of the same city and year, see
+// `MergePolicy::Average`.
+fn average_records(records: impl Iterator<Item = Climate>) -> Climate {
+    let mut records = records.peekable();
+    let (city, year) = match records.peek() {
+        Some(first) => (first.city.clone(), first.year),
+        None => unreachable!("no records to average"),
+    };
+    let (mut temps, mut count) = (0.0, 0);
+    let (mut humidities, mut humidity_count) = (0.0, 0);
+    for record in records {
+        temps += f64::from(record.temp);
+        count += 1;
+        if let Some(humidity) = record.humidity {
+            humidities += f64::from(humidity);
+            humidity_count += 1;
+        }
+    }
+    // Means of valid values are valid too.
+    Climate {
+        city,
+        year,
+        temp: (temps / f64::from(count)) as f32,
+        humidity: (humidity_count > 0).then(|| (humidities / f64::from(humidity_count)) as f32),
+    }
+}
+
+// A record ordered for `extreme_n`: the record that should come first in
+// the result is the least.
+struct Ranked<'a> {
+    record: &'a Climate,
+    warmest_first: bool,
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let by_temp = self.record.temp.total_cmp(&other.record.temp);
+        let by_temp = if self.warmest_first {
+            by_temp.reverse()
+        } else {
+            by_temp
+        };
+        by_temp.then_with(|| self.record.cmp(other.record))
+    }
+}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+// The first `n` of `records` in `Ranked` order. Only `n` records are kept
+// in a heap at a time, whose top is the one that would be dropped next, so
+// this takes O(m log n) time for m records instead of a full sort.
+fn extreme_n<'a>(
+    records: impl Iterator<Item = &'a Climate>,
+    n: usize,
+    warmest_first: bool,
+) -> Vec<&'a Climate> {
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut heap = BinaryHeap::with_capacity(n + 1);
+    for record in records {
+        let ranked = Ranked {
+            record,
+            warmest_first,
+        };
+        if heap.len() < n {
+            heap.push(ranked);
+        } else if heap.peek().is_some_and(|last| ranked < *last) {
+            heap.pop();
+            heap.push(ranked);
+        }
+    }
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|ranked| ranked.record)
+        .collect()
+}
+
+impl FromIterator<Climate> for ClimateDataset {
+    fn from_iter<I: IntoIterator<Item = Climate>>(iter: I) -> Self {
+        let mut dataset = ClimateDataset::new();
+        for record in iter {
+            dataset.insert(record);
+        }
+        dataset
+    }
+}
+
+// Parses every record in the file at `path`. Records that fail to parse are
+// returned separately with their line numbers; an I/O error aborts.
+fn read_climate_file(path: &Path) -> Result<(Vec<Climate>, Vec<LineError>), io::Error> {
+    let reader = ClimateReader::new(io::BufReader::new(File::open(path)?));
+    let mut records = Vec::new();
+    let mut failures = Vec::new();
+    for result in reader {
+        match result {
+            Ok(record) => records.push(record),
+            Err(ClimateReadError::Parse { line, error }) => failures.push((line, error)),
+            Err(ClimateReadError::Io(e)) => return Err(e),
+        }
+    }
+    Ok((records, failures))
+}
+
+// The error returned by `parse_file`: either reading the file failed, or
+// the record on a given line didn't parse.
+type ClimateFileError = ClimateReadError;
+
+// Parses every record in the file at `path`, stopping at the first I/O or
+// parse error. Blank lines are skipped.
+fn parse_file<P: AsRef<Path>>(path: P) -> Result<Vec<Climate>, ClimateFileError> {
+    ClimateReader::new(io::BufReader::new(File::open(path)?)).collect()
+}
+
+// The lines of a file that failed to parse, each with its error, as
+// returned by `main`.
+#[derive(PartialEq)]
+struct BadLinesError {
+    pub failures: Vec<LineError>,
+}
+
+impl Display for BadLinesError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let plural = if self.failures.len() == 1 { "" } else { "s" };
+        write!(f, "{} line{} failed to parse", self.failures.len(), plural)?;
+        for (line, error) in &self.failures {
+            write!(f, "\nline {}: {}", line, error)?;
+        }
+        Ok(())
+    }
+}
+
+// An error returned from `main` is printed with `Debug`, so this gives the
+// same readable list as `Display`.
+impl fmt::Debug for BadLinesError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Error for BadLinesError {}
+
+// With a path argument, prints every record in that file, and fails with a
+// `BadLinesError` if any line failed to parse. Without one, parses a
+// couple of demo records.
+fn main() -> Result<(), Box<dyn Error>> {
+    let path = match env::args_os().nth(1) {
+        Some(path) => path,
+        None => {
+            println!("{:?}", "Hong Kong,1999,25.7".parse::<Climate>()?);
+            println!("{:?}", "".parse::<Climate>()?);
+            return Ok(());
+        }
+    };
+    let (records, failures) = read_climate_file(Path::new(&path))?;
+    for record in &records {
+        println!("{:?}", record);
+    }
+    print!(
+        "{}",
+        records.iter().cloned().collect::<ClimateDataset>().report()
+    );
+    if !failures.is_empty() {
+        return Err(BadLinesError { failures }.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::process;
+
+    // Counts the errors in the chain starting at (and including) `e`.
+    fn chain_len(e: &(dyn Error + 'static)) -> usize {
+        let mut len = 1;
+        let mut current = e;
+        while let Some(next) = current.source() {
+            len += 1;
+            current = next;
+        }
+        len
+    }
+
+    #[test]
+    fn test_source_parse_int() {
+        let err = "Hong Kong,abc,25.7".parse::<Climate>().unwrap_err();
+        let source = err.source().expect("ParseInt should have a source");
+        assert!(source.downcast_ref::<ParseIntError>().is_some());
+        assert_eq!(chain_len(&err), 2);
+    }
+
+    #[test]
+    fn test_source_parse_float() {
+        let err = "Hong Kong,1999,abc".parse::<Climate>().unwrap_err();
+        let source = err.source().expect("ParseFloat should have a source");
+        assert!(source.downcast_ref::<ParseFloatError>().is_some());
+        assert_eq!(chain_len(&err), 2);
+    }
+
+    #[test]
+    fn test_source_none() {
+        assert!(ParseClimateError::Empty.source().is_none());
+        assert!(ParseClimateError::BadLen {
+            expected: 3..=4,
+            found: 2
+        }
+        .source()
+        .is_none());
+        assert!(ParseClimateError::NoCity.source().is_none());
+        assert_eq!(chain_len(&ParseClimateError::Empty), 1);
+    }
+
+    #[test]
+    fn test_boxed_error() {
+        let boxed: Box<dyn Error> = "".parse::<Climate>().unwrap_err().into();
+        assert_eq!(boxed.to_string(), "empty input");
+        assert!(boxed.source().is_none());
+    }
+
+    #[test]
+    fn test_source_matches_inner_error() {
+        let inner = "abc".parse::<u32>().unwrap_err();
+        let err = ParseClimateError::parse_int(inner.clone(), "abc");
+        assert_eq!(
+            err.source().and_then(|e| e.downcast_ref::<ParseIntError>()),
+            Some(&inner)
+        );
+
+        let inner = "abc".parse::<f32>().unwrap_err();
+        let err = ParseClimateError::parse_float(inner.clone(), "abc");
+        assert_eq!(
+            err.source()
+                .and_then(|e| e.downcast_ref::<ParseFloatError>()),
+            Some(&inner)
+        );
+    }
+
+    #[test]
+    fn test_question_mark_into_box() {
+        fn parse(s: &str) -> Result<Climate, Box<dyn Error>> {
+            Ok(s.parse::<Climate>()?)
+        }
+        assert!(parse("Hong Kong,1999,25.7").is_ok());
+        let err = parse("Hong Kong,abc,25.7").unwrap_err();
+        assert!(err.downcast_ref::<ParseClimateError>().is_some());
+        assert!(err
+            .source()
+            .and_then(|e| e.downcast_ref::<ParseIntError>())
+            .is_some());
+    }
+
+    #[test]
+    fn test_lines_ok() {
+        let records = parse_climate_lines("Hong Kong,1999,25.7\nOslo,2020,3.4\n").unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Climate {
+                    city: "Hong Kong".into(),
+                    year: 1999,
+                    temp: 25.7,
+                    humidity: None,
+                },
+                Climate {
+                    city: "Oslo".into(),
+                    year: 2020,
+                    temp: 3.4,
+                    humidity: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lines_error_first_line() {
+        let err = parse_climate_lines("Hong Kong,19x9,25.7\nOslo,2020,3.4").unwrap_err();
+        assert_eq!(err.line, 1);
+        assert_eq!(err.column, 10);
+        assert!(matches!(err.kind, ParseClimateError::ParseInt { .. }));
+        assert_eq!(
+            err.to_string(),
+            "line 1, column 10: error parsing year \"19x9\": invalid digit found in string"
+        );
+    }
+
+    #[test]
+    fn test_lines_error_middle_line() {
+        let input = "Hong Kong,1999,25.7\n\nOslo,2020,3.4";
+        let err = parse_climate_lines(input).unwrap_err();
+        assert_eq!(
+            err,
+            PositionedError {
+                line: 2,
+                column: 0,
+                kind: ParseClimateError::Empty,
+            }
+        );
+    }
+
+    #[test]
+    fn test_lines_error_last_line_without_newline() {
+        let input = "Hong Kong,1999,25.7\nOslo,2020,3.4\nZürich,2001,warm";
+        let err = parse_climate_lines(input).unwrap_err();
+        assert_eq!(err.line, 3);
+        // Columns count characters, not bytes.
+        assert_eq!(err.column, 12);
+        assert!(matches!(err.kind, ParseClimateError::ParseFloat { .. }));
+        assert_eq!(
+            err.to_string(),
+            "line 3, column 12: error parsing temperature \"warm\": invalid float literal"
+        );
+    }
+
+    #[test]
+    fn test_display_year_too_large() {
+        let inner = "99999999999999".parse::<u32>().unwrap_err();
+        let err = "Hong Kong,99999999999999,25.7"
+            .parse::<Climate>()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("error parsing year \"99999999999999\": {}", inner)
+        );
+        assert_eq!(
+            err.to_string(),
+            "error parsing year \"99999999999999\": number too large to fit in target type"
+        );
+    }
+
+    #[test]
+    fn test_display_empty_year() {
+        let err = "Hong Kong,,25.7".parse::<Climate>().unwrap_err();
+        assert_eq!(err, ParseClimateError::MissingYear);
+        assert_eq!(err.to_string(), "year field is empty");
+    }
+
+    #[test]
+    fn test_lines_three_rows() {
+        let input = "Hong Kong,1999,25.7\r\nOslo,2020,3.4\r\nLima,2010,19.2\r\n\r\n";
+        let records = parse_climate_lines(input).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[2].city, "Lima");
+        assert_eq!(records[2].year, 2010);
+        assert_eq!(records[2].temp, 19.2);
+    }
+
+    #[test]
+    fn test_lines_error_on_row_two() {
+        let input = "Hong Kong,1999,25.7\nOslo,2020\nLima,2010,19.2";
+        let err = parse_climate_lines(input).unwrap_err();
+        assert_eq!(err.line, 2);
+        assert_eq!(
+            err.kind,
+            ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_lines_only_newline() {
+        assert_eq!(parse_climate_lines("\n"), Ok(vec![]));
+        assert_eq!(parse_climate_lines("\r\n"), Ok(vec![]));
+        assert_eq!(parse_climate_lines(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(
+            Climate::new("Hong Kong", 1999, 25.7),
+            Ok(Climate {
+                city: "Hong Kong".to_string(),
+                year: 1999,
+                temp: 25.7,
+                humidity: None,
+            })
+        );
+        assert_eq!(
+            Climate::new(String::from("Oslo"), 2020, 3.4),
+            "Oslo,2020,3.4".parse()
+        );
+    }
+
+    #[test]
+    fn test_new_no_city() {
+        assert_eq!(Climate::new("", 1999, 25.7), Err(ParseClimateError::NoCity));
+    }
+
+    #[test]
+    fn test_display_climate() {
+        let climate = Climate::new("Hong Kong", 1999, 25.7).unwrap();
+        assert_eq!(climate.to_string(), "Hong Kong,1999,25.7");
+    }
+
+    #[test]
+    fn test_display_climate_round_trip() {
+        let temps = [25.0, -3.5, 0.125, 25.7, 0.1, -0.0, 1e-7, 3.0e38];
+        for temp in temps {
+            let climate = Climate::new("Hong Kong", 1999, temp).unwrap();
+            let parsed: Climate = climate.to_string().parse().unwrap();
+            assert_eq!(parsed, climate);
+            assert_eq!(parsed.temp.to_bits(), temp.to_bits());
+        }
+    }
+
+    #[test]
+    fn test_delimiter_semicolon() {
+        assert_eq!(
+            Climate::parse_with_delimiter("Hong Kong;1999;25.7", ';'),
+            Climate::new("Hong Kong", 1999, 25.7)
+        );
+    }
+
+    #[test]
+    fn test_delimiter_tab() {
+        assert_eq!(
+            Climate::parse_with_delimiter("Hong Kong\t1999\t25.7", '\t'),
+            Climate::new("Hong Kong", 1999, 25.7)
+        );
+    }
+
+    #[test]
+    fn test_delimiter_multibyte() {
+        assert_eq!(
+            Climate::parse_with_delimiter("Hong Kong；1999；25.7", '；'),
+            Climate::new("Hong Kong", 1999, 25.7)
+        );
+    }
+
+    #[test]
+    fn test_delimiter_rejected() {
+        for delim in ['0', '7', '.', '-'] {
+            assert_eq!(
+                Climate::parse_with_delimiter("Hong Kong,1999,25.7", delim),
+                Err(ParseClimateError::BadDelimiter(delim))
+            );
+        }
+        assert_eq!(
+            ParseClimateError::BadDelimiter('.').to_string(),
+            "'.' cannot be used as a field delimiter"
+        );
+    }
+
+    #[test]
+    fn test_delimiter_default_is_comma() {
+        assert_eq!(
+            "Hong Kong,1999,25.7".parse(),
+            Climate::parse_with_delimiter("Hong Kong,1999,25.7", ',')
+        );
+        assert_eq!(
+            "Hong Kong;1999;25.7".parse::<Climate>(),
+            Err(ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 1
+            })
+        );
+    }
+
+    fn trim_options() -> ParseOptions {
+        ParseOptions {
+            trim_fields: true,
+            ..ParseOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_trim_after_delimiter() {
+        assert_eq!(
+            Climate::parse_with_options("Hong Kong, 1999, 25.7", &trim_options()),
+            Climate::new("Hong Kong", 1999, 25.7)
+        );
+    }
+
+    #[test]
+    fn test_trim_leading_and_trailing() {
+        for s in [
+            " Hong Kong ,\t1999 , 25.7\t",
+            "  Hong Kong,  1999,  25.7",
+            "Hong Kong  ,1999  ,25.7  ",
+            " \"Hong Kong\" ,1999,25.7",
+        ] {
+            assert_eq!(
+                Climate::parse_with_options(s, &trim_options()),
+                Climate::new("Hong Kong", 1999, 25.7)
+            );
+        }
+    }
+
+    #[test]
+    fn test_trim_whitespace_city() {
+        assert_eq!(
+            Climate::parse_with_options(" , 1999, 25.7", &trim_options()),
+            Err(ParseClimateError::NoCity)
+        );
+    }
+
+    #[test]
+    fn test_strict_does_not_trim() {
+        assert!(matches!(
+            "Hong Kong , 1999 , 25.7".parse::<Climate>(),
+            Err(ParseClimateError::ParseInt { .. })
+        ));
+        assert_eq!(
+            "Hong Kong ,1999,25.7".parse(),
+            Climate::new("Hong Kong ", 1999, 25.7)
+        );
+    }
+
+    #[test]
+    fn test_trailing_delimiter() {
+        let options = ParseOptions {
+            allow_trailing_delimiter: true,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            Climate::parse_with_options("a,2001,2.0,", &options),
+            Climate::new("a", 2001, 2.0)
+        );
+        assert_eq!(
+            Climate::parse_with_options("a,2001,2.0", &options),
+            Climate::new("a", 2001, 2.0)
+        );
+        assert_eq!(
+            Climate::parse_with_options("a,2001,2.0,50,", &options),
+            Climate::new("a", 2001, 2.0).and_then(|c| c.with_humidity(50.0))
+        );
+        assert_eq!(
+            Climate::parse_with_options("a,2001,2.0,50,x", &options),
+            Err(ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 5
+            })
+        );
+        // Without the option, the trailing delimiter starts an empty
+        // humidity field.
+        assert_eq!(
+            "a,2001,2.0,".parse::<Climate>(),
+            Err(ParseClimateError::parse_humidity(
+                "".parse::<f32>().unwrap_err(),
+                ""
+            ))
+        );
+    }
+
+    #[test]
+    fn test_trim_and_trailing_delimiter() {
+        let options = ParseOptions {
+            trim_fields: true,
+            allow_trailing_delimiter: true,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            Climate::parse_with_options("Hong Kong , 1999 , 25.7 , ", &options),
+            Climate::new("Hong Kong", 1999, 25.7)
+        );
+        assert_eq!(
+            Climate::parse_with_options("  , 1999 , 25.7 ,", &options),
+            Err(ParseClimateError::NoCity)
+        );
+    }
+
+    #[test]
+    fn test_delimiter_same_record() {
+        let expected = "Oslo,2020,3.4".parse::<Climate>();
+        assert_eq!(
+            Climate::parse_with_delimiter("Oslo\t2020\t3.4", '\t'),
+            expected
+        );
+        assert_eq!(
+            Climate::parse_with_delimiter("Oslo;2020;3.4", ';'),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_delimiter_bad_len() {
+        for (s, delim, found) in [
+            ("Oslo\t2020", '\t', 2),
+            ("Oslo\t2020\t3.4\t1\t2", '\t', 5),
+            ("Oslo;2020", ';', 2),
+            ("Oslo;2020;3.4;1;2", ';', 5),
+            ("Oslo,2020,3.4", ';', 1),
+        ] {
+            assert_eq!(
+                Climate::parse_with_delimiter(s, delim),
+                Err(ParseClimateError::BadLen {
+                    expected: 3..=4,
+                    found
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_quoted_city_with_comma() {
+        assert_eq!(
+            "\"Washington, DC\",1999,25.7".parse(),
+            Climate::new("Washington, DC", 1999, 25.7)
+        );
+        assert_eq!(
+            "\"Hong Kong\",1999,25.7".parse(),
+            Climate::new("Hong Kong", 1999, 25.7)
+        );
+    }
+
+    #[test]
+    fn test_quoted_city_escaped_quote() {
+        assert_eq!(
+            "\"The \"\"Big\"\" Apple\",1999,25.7".parse(),
+            Climate::new("The \"Big\" Apple", 1999, 25.7)
+        );
+    }
+
+    #[test]
+    fn test_quoted_city_unterminated() {
+        let err = "\"Washington, DC,1999,25.7".parse::<Climate>().unwrap_err();
+        assert_eq!(err, ParseClimateError::UnterminatedQuote);
+        assert_eq!(err.to_string(), "unterminated quote in city name");
+    }
+
+    #[test]
+    fn test_quoted_city_bad_len() {
+        assert_eq!(
+            "\"Washington, DC\"".parse::<Climate>(),
+            Err(ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 1
+            })
+        );
+        assert_eq!(
+            "\"Washington, DC\",1999,25.7,1,2".parse::<Climate>(),
+            Err(ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_quoted_city_trailing_text() {
+        assert_eq!(
+            "\"Washington\" DC,1999,25.7".parse(),
+            Climate::new("Washington DC", 1999, 25.7)
+        );
+    }
+
+    #[test]
+    fn test_display_quoted_city_round_trip() {
+        for city in ["Washington, DC", "The \"Big\" Apple"] {
+            let climate = Climate::new(city, 1999, 25.7).unwrap();
+            assert_eq!(climate.to_string().parse(), Ok(climate));
+        }
+    }
+
+    #[test]
+    fn test_not_finite() {
+        for temp in ["NaN", "inf", "-inf"] {
+            let s = format!("Reykjavik,2020,{}", temp);
+            assert_eq!(s.parse::<Climate>(), Err(ParseClimateError::NotFinite));
+        }
+        assert_eq!(
+            ParseClimateError::NotFinite.to_string(),
+            "temperature is not a finite number"
+        );
+    }
+
+    #[test]
+    fn test_new_not_finite() {
+        assert_eq!(
+            Climate::new("Reykjavik", 2020, f32::NAN),
+            Err(ParseClimateError::NotFinite)
+        );
+        assert_eq!(
+            Climate::new("Reykjavik", 2020, f32::INFINITY),
+            Err(ParseClimateError::NotFinite)
+        );
+    }
+
+    #[test]
+    fn test_bad_len_counts() {
+        for (s, found) in [
+            ("Hong Kong", 1),
+            ("Hong Kong,1999", 2),
+            ("Hong Kong,1999,25.7,50,extra", 5),
+            ("a,b", 2),
+            ("a,b,c,d,e", 5),
+            ("a,b,c,d,e,f,g,h,i,j", 10),
+        ] {
+            let err = s.parse::<Climate>().unwrap_err();
+            assert_eq!(
+                err,
+                ParseClimateError::BadLen {
+                    expected: 3..=4,
+                    found
+                }
+            );
+            assert_eq!(
+                err.to_string(),
+                format!(
+                    "incorrect number of fields: expected 3 or 4, found {}",
+                    found
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn test_empty_is_not_bad_len() {
+        assert_eq!("".parse::<Climate>(), Err(ParseClimateError::Empty));
+    }
+
+    #[test]
+    fn test_display_normalizes_input() {
+        for (input, normalized) in [
+            ("Hong Kong,1999,25.7", "Hong Kong,1999,25.7"),
+            ("Oslo,2020,3.40", "Oslo,2020,3.4"),
+            ("Lima,2010,19.0", "Lima,2010,19"),
+            ("Cairo,2001,-0.50", "Cairo,2001,-0.5"),
+            ("\"Washington, DC\",1999,1e1", "\"Washington, DC\",1999,10"),
+        ] {
+            let climate: Climate = input.parse().unwrap();
+            assert_eq!(climate.to_string(), normalized);
+            assert_eq!(normalized.parse::<Climate>().unwrap(), climate);
+        }
+    }
+
+    #[test]
+    fn test_parse_records() {
+        let input = "Hong Kong,1999,25.7\n\nOslo,2020,3.4\n   \nLima,2010,19.2\n";
+        assert_eq!(
+            parse_records(input),
+            Ok(vec![
+                Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+                Climate::new("Oslo", 2020, 3.4).unwrap(),
+                Climate::new("Lima", 2010, 19.2).unwrap(),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_records_bad_year() {
+        let input = "Hong Kong,1999,25.7\nOslo,2020,3.4\nLima,20x0,19.2\nCairo,2001,30.1";
+        let (line, err) = parse_records(input).unwrap_err();
+        assert_eq!(line, 3);
+        assert!(matches!(err, ParseClimateError::ParseInt { .. }));
+    }
+
+    #[test]
+    fn test_parse_records_line_numbers_count_blank_lines() {
+        let input = "\nHong Kong,1999,25.7\n\nOslo,2020";
+        assert_eq!(
+            parse_records(input),
+            Err((
+                4,
+                ParseClimateError::BadLen {
+                    expected: 3..=4,
+                    found: 2
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_reader_clean_file() {
+        let input = "Hong Kong,1999,25.7\r\nOslo,2020,3.4\r\n\r\nLima,2010,19.2";
+        let records: Vec<_> = ClimateReader::new(io::Cursor::new(input.as_bytes()))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+                Climate::new("Oslo", 2020, 3.4).unwrap(),
+                Climate::new("Lima", 2010, 19.2).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reader_bad_row() {
+        let input = "Hong Kong,1999,25.7\nOslo,20x0,3.4\nLima,2010,19.2\n";
+        let mut reader = ClimateReader::new(io::Cursor::new(input.as_bytes()));
+        assert!(reader.next().unwrap().is_ok());
+        match reader.next().unwrap() {
+            Err(ClimateReadError::Parse { line, error }) => {
+                assert_eq!(line, 2);
+                assert!(matches!(error, ParseClimateError::ParseInt { .. }));
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert!(reader.next().unwrap().is_ok());
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_reader_empty_file() {
+        let mut reader = ClimateReader::new(io::Cursor::new(&b""[..]));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_reader_blank_lines_not_skipped() {
+        let input = "Hong Kong,1999,25.7\n\nOslo,2020,3.4\n";
+        let results: Vec<_> = ClimateReader::new(io::Cursor::new(input.as_bytes()))
+            .skip_blank_lines(false)
+            .collect();
+        assert_eq!(results.len(), 3);
+        assert!(matches!(
+            results[1],
+            Err(ClimateReadError::Parse {
+                line: 2,
+                error: ParseClimateError::Empty
+            })
+        ));
+        assert_eq!(
+            results[1].as_ref().unwrap_err().to_string(),
+            "line 2: empty input"
+        );
+    }
+
+    #[test]
+    fn test_temp_units() {
+        let freezing = Climate::new("Oslo", 2020, 0.0).unwrap();
+        let boiling = Climate::new("Oslo", 2020, 100.0).unwrap();
+        assert!((freezing.to_fahrenheit() - 32.0).abs() < 1e-4);
+        assert!((boiling.to_fahrenheit() - 212.0).abs() < 1e-4);
+        assert!((boiling.temp_in(TempUnit::Fahrenheit) - 212.0).abs() < 1e-4);
+        assert_eq!(boiling.temp_in(TempUnit::Celsius), 100.0);
+        assert_eq!(boiling.to_celsius(), 100.0);
+        let cold = Climate::new("Oslo", 2020, -40.0).unwrap();
+        assert!((cold.to_fahrenheit() + 40.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_to_csv_line() {
+        let climate = Climate::new("Washington, DC", 1999, 25.0).unwrap();
+        assert_eq!(climate.to_csv_line(), "\"Washington, DC\",1999,25");
+        let climate = Climate::new("Oslo", 2020, 3.4).unwrap();
+        assert_eq!(climate.to_csv_line(), "Oslo,2020,3.4");
+    }
+
+    #[test]
+    fn test_write_climates_round_trip() {
+        let records = vec![
+            Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+            Climate::new("Washington, DC", 2005, -3.25).unwrap(),
+            Climate::new("Oslo", 2020, 0.1).unwrap(),
+        ];
+        let mut out = Vec::new();
+        write_climates(&mut out, &records).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text,
+            "Hong Kong,1999,25.7\n\"Washington, DC\",2005,-3.25\nOslo,2020,0.1\n"
+        );
+        assert_eq!(parse_records(&text), Ok(records));
+    }
+
+    #[test]
+    fn test_sort_by_city_then_year() {
+        let mut records = [
+            Climate::new("Oslo", 2020, 3.4).unwrap(),
+            Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+            Climate::new("Oslo", 1990, 2.1).unwrap(),
+            Climate::new("Cairo", 2010, 22.0).unwrap(),
+            Climate::new("Oslo", 2005, 2.8).unwrap(),
+        ];
+        records.sort();
+        let order: Vec<_> = records.iter().map(|c| (c.city.as_str(), c.year)).collect();
+        assert_eq!(
+            order,
+            [
+                ("Cairo", 2010),
+                ("Hong Kong", 1999),
+                ("Oslo", 1990),
+                ("Oslo", 2005),
+                ("Oslo", 2020)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sort_same_key_by_temp() {
+        let mut records = [
+            Climate::new("Oslo", 2020, 3.4).unwrap(),
+            Climate::new("Oslo", 2020, -1.5).unwrap(),
+            Climate::new("Oslo", 2020, 0.0).unwrap(),
+            Climate::new("Oslo", 2020, -0.0).unwrap(),
+        ];
+        records.sort();
+        let temps: Vec<_> = records.iter().map(|c| c.temp.to_bits()).collect();
+        let expected: Vec<_> = [-1.5f32, -0.0, 0.0, 3.4]
+            .iter()
+            .map(|t| t.to_bits())
+            .collect();
+        assert_eq!(temps, expected);
+    }
+
+    #[test]
+    fn test_equal_records_compare_equal() {
+        let a = Climate::new("Oslo", 2020, 3.4).unwrap();
+        let b = a.clone();
+        assert_eq!(a.cmp(&b), Ordering::Equal);
+        assert_eq!(a.partial_cmp(&b), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn test_read_climate_file() {
+        let path = env::temp_dir().join(format!("advanced_errs2_{}.csv", process::id()));
+        std::fs::write(
+            &path,
+            "Hong Kong,1999,25.7\nOslo,20x0,3.4\n\nLima,2010,19.2\nCairo\n",
+        )
+        .unwrap();
+        let result = read_climate_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let (records, failures) = result.unwrap();
+        assert_eq!(
+            records,
+            vec![
+                Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+                Climate::new("Lima", 2010, 19.2).unwrap(),
+            ]
+        );
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].0, 2);
+        assert!(matches!(failures[0].1, ParseClimateError::ParseInt { .. }));
+        assert_eq!(
+            failures[1],
+            (
+                5,
+                ParseClimateError::BadLen {
+                    expected: 3..=4,
+                    found: 1
+                }
+            )
+        );
+
+        // This is the error `main` returns for the file.
+        let err: Box<dyn Error> = BadLinesError { failures }.into();
+        let expected = "2 lines failed to parse\n\
+                        line 2: error parsing year \"20x0\": invalid digit found in string\n\
+                        line 5: incorrect number of fields: expected 3 or 4, found 1";
+        assert_eq!(err.to_string(), expected);
+        assert_eq!(format!("{:?}", err), expected);
+        let lines: Vec<usize> = err
+            .downcast_ref::<BadLinesError>()
+            .unwrap()
+            .failures
+            .iter()
+            .map(|&(line, _)| line)
+            .collect();
+        assert_eq!(lines, [2, 5]);
+    }
+
+    #[test]
+    fn test_parse_file() {
+        let path = env::temp_dir().join(format!("advanced_errs2_parse_{}.csv", process::id()));
+        std::fs::write(
+            &path,
+            "Hong Kong,1999,25.7\nOslo,2020,3.4\nLima,2010,19.2\n",
+        )
+        .unwrap();
+        let result = parse_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            result.unwrap(),
+            vec![
+                Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+                Climate::new("Oslo", 2020, 3.4).unwrap(),
+                Climate::new("Lima", 2010, 19.2).unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_file_errors() {
+        let path = env::temp_dir().join("advanced_errs2_does_not_exist.csv");
+        assert!(matches!(
+            parse_file(path),
+            Err(ClimateFileError::Io(e)) if e.kind() == io::ErrorKind::NotFound
+        ));
+
+        let path = env::temp_dir().join(format!("advanced_errs2_bad_{}.csv", process::id()));
+        std::fs::write(&path, "Hong Kong,1999,25.7\nOslo\n").unwrap();
+        let result = parse_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            result,
+            Err(ClimateFileError::Parse {
+                line: 2,
+                error: ParseClimateError::BadLen { found: 1, .. }
+            })
+        ));
+    }
+
+    #[test]
+    fn test_read_climate_file_missing() {
+        let path = env::temp_dir().join("advanced_errs2_does_not_exist.csv");
+        let err = read_climate_file(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        let boxed: Box<dyn Error> = err.into();
+        assert!(boxed.downcast_ref::<io::Error>().is_some());
+    }
+
+    #[test]
+    fn test_year_out_of_range() {
+        for year in [1600, 2200, 0, 999999] {
+            let s = format!("Oslo,{},3.4", year);
+            assert_eq!(
+                s.parse::<Climate>(),
+                Err(ParseClimateError::YearOutOfRange {
+                    year,
+                    min: 1700,
+                    max: 2100
+                })
+            );
+        }
+        assert_eq!(
+            "Oslo,1600,3.4".parse::<Climate>().unwrap_err().to_string(),
+            "year 1600 is outside the range 1700..=2100"
+        );
+    }
+
+    #[test]
+    fn test_year_range_boundaries() {
+        assert!("Oslo,1700,3.4".parse::<Climate>().is_ok());
+        assert!("Oslo,2100,3.4".parse::<Climate>().is_ok());
+        assert_eq!(
+            Climate::new("Oslo", Climate::MIN_YEAR - 1, 3.4),
+            Err(ParseClimateError::YearOutOfRange {
+                year: 1699,
+                min: 1700,
+                max: 2100
+            })
+        );
+        assert_eq!(
+            Climate::new("Oslo", Climate::MAX_YEAR + 1, 3.4),
+            Err(ParseClimateError::YearOutOfRange {
+                year: 2101,
+                min: 1700,
+                max: 2100
+            })
+        );
+    }
+
+    #[test]
+    fn test_unit_suffix() {
+        let climate: Climate = "Oslo,2020,32F".parse().unwrap();
+        assert_eq!(climate.temp, 0.0);
+        let climate: Climate = "Oslo,2020,3.4C".parse().unwrap();
+        assert_eq!(climate.temp, 3.4);
+        let climate: Climate = "Oslo,2020,77.4F".parse().unwrap();
+        assert!((climate.temp - 25.222_221).abs() < 1e-4);
+        // Without a suffix the number is already in Celsius.
+        assert_eq!(
+            "Cairo,2010,30.1".parse::<Climate>(),
+            "Cairo,2010,30.1C".parse::<Climate>()
+        );
+        let climate: Climate = "Cairo,2010,86.2F".parse().unwrap();
+        assert!((climate.temp - 30.111_11).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_unit_conversions() {
+        let climate = Climate::new("Oslo", 2020, 0.0).unwrap();
+        assert_eq!(climate.to_kelvin(), 273.15);
+        assert_eq!(climate.temp_in(TempUnit::Kelvin), 273.15);
+        assert_eq!(TempUnit::Fahrenheit.convert_to_celsius(212.0), 100.0);
+    }
+
+    #[test]
+    fn test_unknown_unit() {
+        let err = "Oslo,2020,25X".parse::<Climate>().unwrap_err();
+        assert_eq!(err, ParseClimateError::UnknownUnit('X'));
+        assert_eq!(err.to_string(), "unknown temperature unit 'X'");
+        // Kelvin and lower-case units aren't accepted as input.
+        for (line, unit) in [("Oslo,2020,300K", 'K'), ("Oslo,2020,3.4c", 'c')] {
+            assert_eq!(
+                line.parse::<Climate>(),
+                Err(ParseClimateError::UnknownUnit(unit))
+            );
+        }
+        assert!(matches!(
+            "Oslo,2020,warm".parse::<Climate>(),
+            Err(ParseClimateError::ParseFloat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_below_absolute_zero() {
+        assert_eq!(
+            "Oslo,2020,-460F".parse::<Climate>(),
+            Err(ParseClimateError::BelowAbsoluteZero)
+        );
+        assert_eq!(
+            "Oslo,2020,-300".parse::<Climate>(),
+            Err(ParseClimateError::BelowAbsoluteZero)
+        );
+        assert!("Oslo,2020,-459F".parse::<Climate>().is_ok());
+    }
+
+    #[test]
+    fn test_five_fields_bad_len() {
+        assert_eq!(
+            "Hong Kong,1999,25.7,1,2".parse::<Climate>(),
+            Err(ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_many_parses() {
+        // Exercises the allocation-free field splitting on a larger batch.
+        for i in 0..10_000u32 {
+            let year = 1800 + i % 300;
+            let temp = i as f32 / 100.0 - 50.0;
+            let line = format!("City {},{},{}", i, year, temp);
+            let climate: Climate = line.parse().unwrap();
+            assert_eq!(climate.year, year);
+            assert_eq!(climate.temp, temp);
+        }
+    }
+
+    #[test]
+    fn test_not_finite_spellings() {
+        for temp in [
+            "nan",
+            "NaN",
+            "NAN",
+            "inf",
+            "-inf",
+            "+inf",
+            "INF",
+            "infinity",
+            "Infinity",
+            "-INFINITY",
+            "infF",
+            "nanC",
+        ] {
+            let s = format!("City,2000,{}", temp);
+            assert_eq!(
+                s.parse::<Climate>(),
+                Err(ParseClimateError::NotFinite),
+                "{}",
+                temp
+            );
+        }
+    }
+
+    #[test]
+    fn test_large_finite_temp() {
+        let climate: Climate = "City,2000,3.0e38".parse().unwrap();
+        assert_eq!(climate.temp, 3.0e38);
+    }
+
+    #[test]
+    fn test_try_from_slice() {
+        let fields: &[&str] = &["Hong Kong", "1999", "25.7"];
+        assert_eq!(
+            Climate::try_from(fields),
+            Climate::new("Hong Kong", 1999, 25.7)
+        );
+        let fields: &[&str] = &["", "1999", "25.7"];
+        assert_eq!(Climate::try_from(fields), Err(ParseClimateError::NoCity));
+    }
+
+    #[test]
+    fn test_try_from_slice_bad_len() {
+        let fields: &[&str] = &["Hong Kong", "1999"];
+        assert_eq!(
+            Climate::try_from(fields),
+            Err(ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 2
+            })
+        );
+        let fields: &[&str] = &["Hong Kong", "1999", "25.7", "50", "extra"];
+        assert_eq!(
+            Climate::try_from(fields),
+            Err(ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 5
+            })
+        );
+    }
+
+    #[test]
+    fn test_year_range_override() {
+        let options = ParseOptions {
+            years: 1000..=2100,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            Climate::parse_with_options("Oslo,1000,3.4", &options).map(|c| c.year),
+            Ok(1000)
+        );
+        assert_eq!(
+            Climate::parse_with_options("Oslo,1699,3.4", &options).map(|c| c.year),
+            Ok(1699)
+        );
+        let err = Climate::parse_with_options("Oslo,999,3.4", &options).unwrap_err();
+        assert_eq!(
+            err,
+            ParseClimateError::YearOutOfRange {
+                year: 999,
+                min: 1000,
+                max: 2100
+            }
+        );
+        assert_eq!(err.to_string(), "year 999 is outside the range 1000..=2100");
+    }
+
+    #[test]
+    fn test_all_errors_one_field() {
+        let errors = Climate::parse_all_errors("Oslo,2020,warm").unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], ParseClimateError::ParseFloat { .. }));
+    }
+
+    #[test]
+    fn test_all_errors_several_fields() {
+        let errors = Climate::parse_all_errors(",20x0,warm").unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0], ParseClimateError::NoCity);
+        assert!(matches!(errors[1], ParseClimateError::ParseInt { .. }));
+        assert!(matches!(errors[2], ParseClimateError::ParseFloat { .. }));
+
+        let errors = Climate::parse_all_errors("Oslo,1600,inf").unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                ParseClimateError::YearOutOfRange {
+                    year: 1600,
+                    min: 1700,
+                    max: 2100
+                },
+                ParseClimateError::NotFinite,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_all_errors_structural() {
+        assert_eq!(
+            Climate::parse_all_errors(""),
+            Err(vec![ParseClimateError::Empty])
+        );
+        assert_eq!(
+            Climate::parse_all_errors("Oslo,x"),
+            Err(vec![ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 2
+            }])
+        );
+    }
+
+    #[test]
+    fn test_all_errors_clean() {
+        assert_eq!(
+            Climate::parse_all_errors("Oslo,2020,3.4"),
+            Climate::new("Oslo", 2020, 3.4).map_err(|e| vec![e])
+        );
+    }
+
+    #[test]
+    fn test_missing_temp() {
+        let err = "Paris,1999,".parse::<Climate>().unwrap_err();
+        assert_eq!(err, ParseClimateError::MissingTemp);
+        assert_eq!(err.to_string(), "temperature field is empty");
+        assert_eq!(
+            "Paris,1999, ".parse::<Climate>(),
+            Err(ParseClimateError::MissingTemp)
+        );
+    }
+
+    #[test]
+    fn test_missing_year_whitespace() {
+        assert_eq!(
+            "Paris, ,25.7".parse::<Climate>(),
+            Err(ParseClimateError::MissingYear)
+        );
+    }
+
+    #[test]
+    fn test_invalid_fields_still_parse_errors() {
+        assert!(matches!(
+            "Paris,abc,25.7".parse::<Climate>(),
+            Err(ParseClimateError::ParseInt { .. })
+        ));
+        assert!(matches!(
+            "Paris,1999,abc".parse::<Climate>(),
+            Err(ParseClimateError::ParseFloat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_average_temp() {
+        assert_eq!(average_temp(&[]), None);
+        let one = [Climate::new("Oslo", 2020, 3.4).unwrap()];
+        assert_eq!(average_temp(&one), Some(3.4));
+        let three = [
+            Climate::new("Oslo", 2020, 3.4).unwrap(),
+            Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+            Climate::new("Lima", 2010, 19.2).unwrap(),
+        ];
+        assert!((average_temp(&three).unwrap() - 16.1).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_stats() {
+        let stats: ClimateStats = [
+            Climate::new("Oslo", 2020, 3.5).unwrap(),
+            Climate::new("Hong Kong", 1999, 25.5).unwrap(),
+            Climate::new("Lima", 2010, 18.0).unwrap(),
+            Climate::new("Reykjavik", 2005, -1.0).unwrap(),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(stats.count(), 4);
+        assert_eq!(stats.min_temp(), Some(-1.0));
+        assert_eq!(stats.max_temp(), Some(25.5));
+        assert_eq!(stats.mean_temp(), Some(11.5));
+        assert_eq!(stats.year_span(), Some(1999..=2020));
+    }
+
+    #[test]
+    fn test_stats_push() {
+        let mut stats = ClimateStats::new();
+        stats.push(&Climate::new("Oslo", 2020, 3.4).unwrap());
+        assert_eq!(stats.count(), 1);
+        assert_eq!(stats.min_temp(), Some(3.4));
+        assert_eq!(stats.max_temp(), Some(3.4));
+        assert_eq!(stats.mean_temp(), Some(3.4));
+        assert_eq!(stats.year_span(), Some(2020..=2020));
+    }
+
+    #[test]
+    fn test_stats_by_reference() {
+        let records = [
+            Climate::new("Oslo", 2020, 3.5).unwrap(),
+            Climate::new("Hong Kong", 1999, 25.5).unwrap(),
+            Climate::new("Lima", 2010, 18.0).unwrap(),
+        ];
+        let borrowed: ClimateStats = records.iter().collect();
+        assert_eq!(borrowed, records.iter().cloned().collect::<ClimateStats>());
+        let mut extended: ClimateStats = records[..1].iter().collect();
+        extended.extend(&records[1..]);
+        assert_eq!(extended, borrowed);
+        assert_eq!(borrowed.count(), 3);
+        assert_eq!(borrowed.year_span(), Some(1999..=2020));
+    }
+
+    #[test]
+    fn test_stats_empty() {
+        let stats: ClimateStats = Vec::<Climate>::new().into_iter().collect();
+        assert_eq!(stats.count(), 0);
+        assert_eq!(stats.min_temp(), None);
+        assert_eq!(stats.max_temp(), None);
+        assert_eq!(stats.mean_temp(), None);
+        assert_eq!(stats.year_span(), None);
+    }
+
+    #[test]
+    fn test_warmest_coldest() {
+        assert_eq!(warmest(&[]), None);
+        assert_eq!(coldest(&[]), None);
+        let records = [
+            Climate::new("Oslo", 2020, 3.4).unwrap(),
+            Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+            Climate::new("Reykjavik", 2005, -1.0).unwrap(),
+        ];
+        assert_eq!(warmest(&records).unwrap().city, "Hong Kong");
+        assert_eq!(coldest(&records).unwrap().city, "Reykjavik");
+    }
+
+    #[test]
+    fn test_warmest_coldest_tie_break() {
+        let records = [
+            Climate::new("Cairo", 2010, 30.0).unwrap(),
+            Climate::new("Lima", 2001, -5.0).unwrap(),
+            Climate::new("Dubai", 2003, 30.0).unwrap(),
+            Climate::new("Oslo", 1990, -5.0).unwrap(),
+            Climate::new("Riyadh", 2003, 30.0).unwrap(),
+        ];
+        assert_eq!(warmest(&records).unwrap().city, "Dubai");
+        assert_eq!(coldest(&records).unwrap().city, "Oslo");
+    }
+
+    fn sample_dataset() -> ClimateDataset {
+        [
+            Climate::new("Oslo", 2020, 3.4).unwrap(),
+            Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+            Climate::new("Oslo", 1990, 2.1).unwrap(),
+            Climate::new("Hong Kong", 1995, 24.9).unwrap(),
+            Climate::new("Oslo", 2005, 2.8).unwrap(),
+        ]
+        .into_iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_dataset_cities() {
+        assert!(ClimateDataset::new().is_empty());
+        let dataset = sample_dataset();
+        assert_eq!(dataset.len(), 5);
+        assert_eq!(dataset.cities(), ["Hong Kong", "Oslo"]);
+        assert_eq!(dataset.records_for("Hong Kong").len(), 2);
+        assert!(dataset.records_for("Lima").is_empty());
+        let cities: Vec<_> = dataset
+            .iter()
+            .map(|(city, records)| (city, records.len()))
+            .collect();
+        assert_eq!(cities, [("Hong Kong", 2), ("Oslo", 3)]);
+    }
+
+    #[test]
+    fn test_dataset_sorted_by_year() {
+        let dataset = sample_dataset();
+        assert_eq!(dataset.years_for("Oslo"), [1990, 2005, 2020]);
+        assert_eq!(dataset.years_for("Hong Kong"), [1995, 1999]);
+        assert!(dataset.duplicates().is_empty());
+    }
+
+    #[test]
+    fn test_dataset_duplicates() {
+        let mut dataset = sample_dataset();
+        dataset.insert(Climate::new("Oslo", 2005, 3.0).unwrap());
+        dataset.insert(Climate::new("Oslo", 2005, 3.1).unwrap());
+        assert_eq!(dataset.duplicates(), [("Oslo", 2005)]);
+        let temps: Vec<_> = dataset.records_for("Oslo").iter().map(|r| r.temp).collect();
+        assert_eq!(temps, [2.1, 2.8, 3.0, 3.1, 3.4]);
+    }
+
+    #[test]
+    fn test_dataset_sort_by_year_and_temp() {
+        let dataset = sample_dataset();
+        let years: Vec<_> = dataset.sort_by_year().iter().map(|r| r.year).collect();
+        assert_eq!(years, [1990, 1995, 1999, 2005, 2020]);
+        let temps: Vec<_> = dataset.sort_by_temp().iter().map(|r| r.temp).collect();
+        assert_eq!(temps, [2.1, 2.8, 3.4, 24.9, 25.7]);
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        let a = Climate::new("Hong Kong", 1999, 25.7).unwrap();
+        let b = Climate::new("Hong Kong", 1999, 25.70001).unwrap();
+        assert_ne!(a, b);
+        assert!(a.approx_eq(&b, 1e-3));
+        assert!(!a.approx_eq(&b, 1e-9));
+        let other_year = Climate::new("Hong Kong", 2000, 25.7).unwrap();
+        assert!(!a.approx_eq(&other_year, 1e-3));
+    }
+
+    #[test]
+    fn test_key_borrowed_lookup() {
+        let record = Climate::new("Oslo", 2020, 3.4).unwrap();
+        let mut temps = HashMap::new();
+        temps.insert(record.key(), record.temp);
+        assert_eq!(temps.get::<dyn ClimateKeyRef>(&("Oslo", 2020)), Some(&3.4));
+        assert_eq!(temps.get::<dyn ClimateKeyRef>(&("Oslo", 2021)), None);
+        assert_eq!(temps.get(&record.key()), Some(&3.4));
+
+        let mut ordered = BTreeMap::new();
+        ordered.insert(record.key(), record.temp);
+        assert!(ordered.contains_key::<dyn ClimateKeyRef>(&("Oslo", 2020)));
+    }
+
+    #[test]
+    fn test_dedup_by_key() {
+        let records = vec![
+            Climate::new("Oslo", 2020, 3.4).unwrap(),
+            Climate::new("Lima", 2020, 19.2).unwrap(),
+            Climate::new("Oslo", 2020, 3.9).unwrap(),
+        ];
+        let deduped = dedup_by_key(records);
+        assert_eq!(deduped.len(), 2);
+        assert_eq!(deduped[0].temp, 3.4);
+        assert_eq!(deduped[1].city, "Lima");
+    }
+
+    #[test]
+    fn test_table_reordered_header() {
+        let input = "Year,TEMP,city\n1999,25.7,Hong Kong\n\n2020,3.4,Oslo\n";
+        assert_eq!(
+            ClimateTable::parse(input),
+            Ok(vec![
+                Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+                Climate::new("Oslo", 2020, 3.4).unwrap(),
+            ])
+        );
+        assert_eq!(
+            ClimateTable::parse("year,temp,city\n1999,abc,Hong Kong\n")
+                .unwrap_err()
+                .0,
+            2
+        );
+    }
+
+    #[test]
+    fn test_table_header_errors() {
+        assert_eq!(
+            ClimateTable::parse("city,year,temp,pressure\nOslo,2020,3.4,1013\n"),
+            Err((1, ParseClimateError::UnknownColumn("pressure".into())))
+        );
+        assert_eq!(
+            ClimateTable::parse("city,year\nOslo,2020\n"),
+            Err((1, ParseClimateError::MissingColumn("temp")))
+        );
+        assert_eq!(
+            ClimateTable::parse("city,year,year\n"),
+            Err((1, ParseClimateError::UnknownColumn("year".into())))
+        );
+    }
+
+    #[test]
+    fn test_table_without_header() {
+        let input = "\"Paris, France\",2001,12.1\nOslo,2020,3.4";
+        assert_eq!(
+            ClimateTable::parse(input),
+            Ok(vec![
+                Climate::new("Paris, France", 2001, 12.1).unwrap(),
+                Climate::new("Oslo", 2020, 3.4).unwrap(),
+            ])
+        );
+        assert_eq!(ClimateTable::parse(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_builder() {
+        let built = ClimateBuilder::new()
+            .temp(25.7)
+            .city("Hong Kong")
+            .year(1999)
+            .build();
+        assert_eq!(built, Ok(Climate::new("Hong Kong", 1999, 25.7).unwrap()));
+        assert_eq!(
+            ClimateBuilder::new().city("").year(1999).temp(25.7).build(),
+            Err(vec![ParseClimateError::NoCity])
+        );
+        assert_eq!(
+            ClimateBuilder::new().city("Oslo").year(1).temp(3.4).build(),
+            Err(vec![ParseClimateError::YearOutOfRange {
+                year: 1,
+                min: Climate::MIN_YEAR,
+                max: Climate::MAX_YEAR
+            }])
+        );
+    }
+
+    #[test]
+    fn test_builder_missing_fields() {
+        assert_eq!(
+            ClimateBuilder::new().year(1999).temp(25.7).build(),
+            Err(vec![ParseClimateError::MissingField("city")])
+        );
+        assert_eq!(
+            ClimateBuilder::new().city("Oslo").temp(3.4).build(),
+            Err(vec![ParseClimateError::MissingField("year")])
+        );
+        assert_eq!(
+            ClimateBuilder::new().city("Oslo").year(2020).build(),
+            Err(vec![ParseClimateError::MissingField("temp")])
+        );
+        assert_eq!(
+            ClimateBuilder::new().build(),
+            Err(vec![
+                ParseClimateError::MissingField("city"),
+                ParseClimateError::MissingField("year"),
+                ParseClimateError::MissingField("temp"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_humidity_field() {
+        let climate: Climate = "Hong Kong,1999,25.7,78.5".parse().unwrap();
+        assert_eq!(climate.humidity, Some(78.5));
+        assert_eq!(climate.to_string(), "Hong Kong,1999,25.7,78.5");
+        assert_eq!(
+            Climate::try_from(&["Hong Kong", "1999", "25.7", "78.5"][..]),
+            Ok(climate)
+        );
+
+        let climate: Climate = "Hong Kong,1999,25.7".parse().unwrap();
+        assert_eq!(climate.humidity, None);
+        assert_eq!(climate.to_string(), "Hong Kong,1999,25.7");
+    }
+
+    #[test]
+    fn test_humidity_errors() {
+        assert_eq!(
+            "Oslo,2020,3.4,100.5".parse::<Climate>(),
+            Err(ParseClimateError::HumidityOutOfRange(100.5))
+        );
+        assert_eq!(
+            "Oslo,2020,3.4,-1".parse::<Climate>(),
+            Err(ParseClimateError::HumidityOutOfRange(-1.0))
+        );
+        let err = "Oslo,2020,3.4,damp".parse::<Climate>().unwrap_err();
+        assert!(matches!(err, ParseClimateError::ParseHumidity { .. }));
+        assert_eq!(
+            err.to_string(),
+            "error parsing humidity \"damp\": invalid float literal"
+        );
+        let err = parse_climate_lines("Oslo,2020,3.4,abc").unwrap_err();
+        assert_eq!(err.column, 14);
+        assert!(err
+            .to_string()
+            .starts_with("line 1, column 14: error parsing humidity \"abc\""));
+        assert!("Oslo,2020,3.4,0".parse::<Climate>().is_ok());
+        assert!("Oslo,2020,3.4,100".parse::<Climate>().is_ok());
+        assert_eq!(
+            ClimateBuilder::new()
+                .city("Oslo")
+                .year(2020)
+                .temp(3.4)
+                .humidity(101.0)
+                .build(),
+            Err(vec![ParseClimateError::HumidityOutOfRange(101.0)])
+        );
+    }
+
+    #[test]
+    fn test_table_humidity_column() {
+        let input = "humidity,city,year,temp\n80,Oslo,2020,3.4\n";
+        assert_eq!(
+            ClimateTable::parse(input),
+            Ok(vec![Climate::new("Oslo", 2020, 3.4)
+                .unwrap()
+                .with_humidity(80.0)
+                .unwrap()])
+        );
+        assert_eq!(
+            ClimateTable::parse("city,year,temp\nOslo,2020,3.4,80\n"),
+            Err((
+                2,
+                ParseClimateError::BadLen {
+                    expected: 3..=3,
+                    found: 4
+                }
+            ))
+        );
+        assert_eq!(
+            ClimateTable::parse("city,year,temp,humidity\nOslo,2020,3.4\n")
+                .unwrap_err()
+                .1
+                .to_string(),
+            "incorrect number of fields: expected 4, found 3"
+        );
+        assert_eq!(
+            ClimateTable::parse("city,year,temp\nOslo,2020,3.4,80\n")
+                .unwrap_err()
+                .1
+                .to_string(),
+            "incorrect number of fields: expected 3, found 4"
+        );
+        assert_eq!(
+            ClimateTable::parse("year,city,temp\n2020,Oslo,3.4,80,1\n"),
+            Err((
+                2,
+                ParseClimateError::BadLen {
+                    expected: 3..=3,
+                    found: 5
+                }
+            ))
+        );
+        assert_eq!(
+            ClimateTable::parse("city,year,temp\nOslo,2020,3.4,80,1\n"),
+            Err((
+                2,
+                ParseClimateError::BadLen {
+                    expected: 3..=3,
+                    found: 5
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_records_iter() {
+        let input = "Hong Kong,1999,25.7\nOslo,x,3.4\n\nLima,2010,19.2\nCairo\nOslo,2020,3.4\n";
+        let results: Vec<_> = records_iter(input).collect();
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0], Climate::new("Hong Kong", 1999, 25.7));
+        assert!(matches!(
+            results[1],
+            Err(ParseClimateError::ParseInt { .. })
+        ));
+        assert_eq!(results[2], Climate::new("Lima", 2010, 19.2));
+        assert_eq!(
+            results[3],
+            Err(ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 1
+            })
+        );
+        assert_eq!(results[4], Climate::new("Oslo", 2020, 3.4));
+        assert_eq!(records_iter(input).filter_map(Result::ok).count(), 3);
+    }
+
+    #[test]
+    fn test_key_set_dedup() {
+        let a = Climate::new("Oslo", 2020, 3.4).unwrap();
+        let b = Climate::new("Oslo", 2020, 3.9).unwrap();
+        assert_ne!(a, b);
+        let keys: HashSet<ClimateKey> = [&a, &b].iter().map(|c| c.key()).collect();
+        assert_eq!(keys.len(), 1);
+        assert!(keys.contains(&b.key()));
+    }
+
+    #[test]
+    fn test_parser_borrowed() {
+        let line = "Hong Kong,1999,25.7";
+        let parsed = ClimateParser::new().parse_borrowed(line).unwrap();
+        assert!(matches!(parsed.city, Cow::Borrowed("Hong Kong")));
+        assert_eq!(parsed.to_owned(), line.parse().unwrap());
+
+        let parsed = ClimateParser::new()
+            .parse_borrowed("\"Paris, \"\"France\"\"\",2001,12.1")
+            .unwrap();
+        assert_eq!(parsed.city, "Paris, \"France\"");
+    }
+
+    #[test]
+    fn test_parser_into() {
+        let parser = ClimateParser::new();
+        let mut out = Climate::new("Some long city name", 2000, 0.0).unwrap();
+        let capacity = out.city.capacity();
+        parser.parse_into("Oslo,2020,3.4,80", &mut out).unwrap();
+        assert_eq!(out, "Oslo,2020,3.4,80".parse().unwrap());
+        assert_eq!(out.city.capacity(), capacity);
+
+        assert!(parser.parse_into("Lima,x,1.0", &mut out).is_err());
+        assert_eq!(out, "Oslo,2020,3.4,80".parse().unwrap());
+
+        let trimming = ClimateParser::with_options(trim_options());
+        trimming
+            .parse_into(" Lima , 2010 , 19.2 ", &mut out)
+            .unwrap();
+        assert_eq!(out, Climate::new("Lima", 2010, 19.2).unwrap());
+    }
+
+    #[test]
+    fn test_parser_agrees_with_from_str() {
+        let parser = ClimateParser::new();
+        for line in [
+            "",
+            "Hong Kong",
+            "Hong Kong,1999",
+            "Hong Kong,1999,25.7,50,1",
+            ",1999,25.7",
+            "Hong Kong,,25.7",
+            "Hong Kong,1999,",
+            "Hong Kong,x,25.7",
+            "Hong Kong,1999,x",
+            "Hong Kong,1600,25.7",
+            "Hong Kong,1999,inf",
+            "Hong Kong,1999,-300",
+            "Hong Kong,1999,25.7X",
+            "Hong Kong,1999,25.7,101",
+            "\"Hong Kong,1999,25.7",
+            "Hong Kong,1999,25.7",
+        ] {
+            let expected = line.parse::<Climate>();
+            assert_eq!(
+                parser.parse_borrowed(line).map(ClimateRef::into_owned),
+                expected,
+                "{:?}",
+                line
+            );
+            let mut out = Climate::new("x", 2000, 0.0).unwrap();
+            assert_eq!(
+                parser.parse_into(line, &mut out).map(|()| out),
+                expected,
+                "{:?}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_interned_cities_shared() {
+        let mut interner = CityInterner::new();
+        assert!(interner.is_empty());
+        let records: Vec<_> = ["Oslo,2020,3.4", "Lima,2010,19.2", "Oslo,2021,3.9"]
+            .iter()
+            .map(|line| interner.parse_interned(line).unwrap())
+            .collect();
+        assert_eq!(interner.len(), 2);
+        assert!(Arc::ptr_eq(&records[0].city, &records[2].city));
+        assert!(!Arc::ptr_eq(&records[0].city, &records[1].city));
+        assert!(Arc::ptr_eq(&interner.intern("Oslo"), &records[0].city));
+        assert_eq!(
+            interner.parse_interned("Oslo,x,3.4"),
+            "Oslo,x,3.4".parse::<Climate>().map(InternedClimate::from)
+        );
+    }
+
+    #[test]
+    fn test_interned_conversions() {
+        let climate: Climate = "Oslo,2020,3.4,80".parse().unwrap();
+        let interned = InternedClimate::from(climate.clone());
+        assert_eq!(&*interned.city, "Oslo");
+        assert_eq!(interned.humidity, Some(80.0));
+        assert_eq!(Climate::from(interned), climate);
+    }
+
+    #[test]
+    fn test_avg_temp_by_year() {
+        let records = [
+            Climate::new("Oslo", 2020, 3.0).unwrap(),
+            Climate::new("Lima", 2010, 19.0).unwrap(),
+            Climate::new("Cairo", 2020, 22.0).unwrap(),
+            Climate::new("Oslo", 2010, 2.0).unwrap(),
+            Climate::new("Lima", 2020, 20.0).unwrap(),
+        ];
+        let averages = avg_temp_by_year(&records);
+        assert_eq!(averages.keys().copied().collect::<Vec<_>>(), [2010, 2020]);
+        assert_eq!(averages[&2010], 10.5);
+        assert_eq!(averages[&2020], 15.0);
+        assert!(avg_temp_by_year(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_parse_allow_missing() {
+        let parsed = Climate::parse_allow_missing("Oslo,2001,NA").unwrap();
+        assert_eq!(parsed.city, "Oslo");
+        assert_eq!(parsed.year, 2001);
+        assert_eq!(parsed.temp, None);
+        assert_eq!(
+            Climate::parse_allow_missing("Oslo,2001,").unwrap().temp,
+            None
+        );
+        assert_eq!(
+            Climate::parse_allow_missing("Oslo,2001,4.1").unwrap().temp,
+            Some(4.1)
+        );
+        assert_eq!(
+            "Oslo,2001,NA".parse::<Climate>(),
+            Err(ParseClimateError::parse_float(
+                "NA".parse::<f32>().unwrap_err(),
+                "NA"
+            ))
+        );
+        assert_eq!(
+            "Oslo,2001,".parse::<Climate>(),
+            Err(ParseClimateError::MissingTemp)
+        );
+    }
+
+    #[test]
+    fn test_parse_allow_missing_errors() {
+        assert_eq!(
+            Climate::parse_allow_missing(",2001,NA"),
+            Err(ParseClimateError::NoCity)
+        );
+        assert_eq!(
+            Climate::parse_allow_missing("Oslo,2001,-300"),
+            Err(ParseClimateError::BelowAbsoluteZero)
+        );
+        assert!(matches!(
+            Climate::parse_allow_missing("Oslo,2001,n/a"),
+            Err(ParseClimateError::ParseFloat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_city_eq_ignore_case() {
+        let climate = Climate::new("Paris", 2001, 12.1).unwrap();
+        for other in ["paris", "Paris", "PARIS"] {
+            assert!(climate.city_eq_ignore_case(other), "{}", other);
+        }
+        assert!(!climate.city_eq_ignore_case("Pari"));
+        let climate = Climate::new("Zürich", 2001, 9.3).unwrap();
+        assert!(climate.city_eq_ignore_case("ZÜRICH"));
+    }
+
+    #[test]
+    fn test_normalize_city() {
+        let mut climate = Climate::new("new  york", 2001, 12.1).unwrap();
+        climate.normalize_city();
+        assert_eq!(climate.city, "New York");
+        for (city, normalized) in [("PARIS", "Paris"), ("  rio de\tJANEIRO ", "Rio De Janeiro")] {
+            let mut climate = Climate::new(city, 2001, 12.1).unwrap();
+            climate.normalize_city();
+            assert_eq!(climate.city, normalized);
+        }
+    }
+
+    // Feeds `chunks` to a new decoder, draining it after every push.
+    fn decode_chunks<'a>(
+        chunks: impl IntoIterator<Item = &'a [u8]>,
+    ) -> Vec<Result<Climate, ParseClimateError>> {
+        let mut decoder = ClimateDecoder::new();
+        let mut records = Vec::new();
+        for chunk in chunks {
+            decoder.push(chunk);
+            records.extend(std::iter::from_fn(|| decoder.next_record()));
+        }
+        decoder.finish();
+        records.extend(std::iter::from_fn(|| decoder.next_record()));
+        records
+    }
+
+    #[test]
+    fn test_decoder_chunking() {
+        let input = "Zürich,2001,9.3\r\n\nOslo,20x0,3.4\nSão Paulo,2010,19.2".as_bytes();
+        let whole = decode_chunks([input]);
+        assert_eq!(whole.len(), 3);
+        assert_eq!(whole[0], Climate::new("Zürich", 2001, 9.3));
+        assert!(matches!(whole[1], Err(ParseClimateError::ParseInt { .. })));
+        assert_eq!(whole[2], Climate::new("São Paulo", 2010, 19.2));
+
+        assert_eq!(decode_chunks(input.chunks(1)), whole);
+        // Splits inside the two-byte "ü" and "ã".
+        let u = input.iter().position(|&b| b == 0xc3).unwrap();
+        let a = input.iter().rposition(|&b| b == 0xc3).unwrap();
+        let split = [&input[..u + 1], &input[u + 1..a + 1], &input[a + 1..]];
+        assert_eq!(decode_chunks(split), whole);
+    }
+
+    #[test]
+    fn test_decoder_invalid_utf8() {
+        let records = decode_chunks([&b"Oslo,2020,3.4\nOsl\xff,2020,3.4\nLima,2010,19.2\n"[..]]);
+        assert_eq!(records.len(), 3);
+        let err = records[1].as_ref().unwrap_err();
+        assert!(matches!(err, ParseClimateError::InvalidUtf8(_)));
+        assert!(err.source().is_some());
+        assert_eq!(records[2], Climate::new("Lima", 2010, 19.2));
+
+        let mut decoder = ClimateDecoder::new();
+        decoder.push(b"Oslo,2020,3.4");
+        assert_eq!(decoder.next_record(), None);
+        decoder.finish();
+        assert_eq!(decoder.next_record(), Some(Climate::new("Oslo", 2020, 3.4)));
+        assert_eq!(decoder.next_record(), None);
+    }
+
+    fn binary_samples() -> Vec<Climate> {
+        let wide = 0..=u32::MAX;
+        vec![
+            Climate::new_with_years("Oslo", 0, -40.5, &wide).unwrap(),
+            Climate::new_with_years("A", u32::MAX, ABSOLUTE_ZERO, &wide).unwrap(),
+            Climate::new("Llanfairpwllgwyngyll 🌧 Καλαμάτα 東京", 2020, -0.0)
+                .unwrap()
+                .with_humidity(100.0)
+                .unwrap(),
+            Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        for record in binary_samples() {
+            let mut bytes = Vec::new();
+            record.write_binary(&mut bytes).unwrap();
+            let read = Climate::read_binary(&mut &bytes[..]).unwrap();
+            assert_eq!(read, record);
+            assert_eq!(read.temp.to_bits(), record.temp.to_bits());
+        }
+
+        let records = binary_samples();
+        let mut bytes = Vec::new();
+        write_binary_stream(&mut bytes, &records).unwrap();
+        assert_eq!(bytes[0], BINARY_VERSION);
+        assert_eq!(read_binary_stream(&mut &bytes[..]).unwrap(), records);
+
+        let mut bytes = Vec::new();
+        write_binary_stream(&mut bytes, &[]).unwrap();
+        assert_eq!(read_binary_stream(&mut &bytes[..]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn test_binary_errors() {
+        let mut bytes = Vec::new();
+        write_binary_stream(&mut bytes, &binary_samples()).unwrap();
+        for len in [0, 1, 4, 7, bytes.len() - 1] {
+            assert!(
+                matches!(
+                    read_binary_stream(&mut &bytes[..len]),
+                    Err(BinaryError::UnexpectedEof)
+                ),
+                "{}",
+                len
+            );
+        }
+        bytes[0] = 7;
+        assert!(matches!(
+            read_binary_stream(&mut &bytes[..]),
+            Err(BinaryError::UnsupportedVersion(7))
+        ));
+
+        let mut bytes = Vec::new();
+        Climate::new("Oslo", 2020, 3.4)
+            .unwrap()
+            .write_binary(&mut bytes)
+            .unwrap();
+        bytes[2] = 0xff;
+        assert!(matches!(
+            Climate::read_binary(&mut &bytes[..]),
+            Err(BinaryError::Invalid(ParseClimateError::InvalidUtf8(_)))
+        ));
+        let last = bytes.len() - 1;
+        bytes[last] = 2;
+        bytes[2] = b'O';
+        assert!(matches!(
+            Climate::read_binary(&mut &bytes[..]),
+            Err(BinaryError::BadTag(2))
+        ));
+
+        let long = Climate::new("x".repeat(70_000), 2020, 3.4).unwrap();
+        let err = long.write_binary(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_to_json_array() {
+        assert_eq!(to_json_array(&[]), "[]");
+        assert_eq!(
+            to_json_array(&[Climate::new("Hong Kong", 1999, 25.7).unwrap()]),
+            r#"[{"city":"Hong Kong","year":1999,"temp":25.7}]"#
+        );
+        let records = [
+            Climate::new("The \"Big\" Apple", 2001, -3.5).unwrap(),
+            Climate::new("back\\slash\ttab\u{1}", 2002, 0.0)
+                .unwrap()
+                .with_humidity(55.5)
+                .unwrap(),
+        ];
+        assert_eq!(
+            to_json_array(&records),
+            r#"[{"city":"The \"Big\" Apple","year":2001,"temp":-3.5},"#.to_string()
+                + r#"{"city":"back\\slash\ttab\u0001","year":2002,"temp":0,"humidity":55.5}]"#
+        );
+    }
+
+    #[test]
+    fn test_to_json() {
+        let climate = Climate::new("Oslo", 2020, 3.4).unwrap();
+        assert_eq!(
+            climate.to_json(),
+            r#"{"city":"Oslo","year":2020,"temp":3.4}"#
+        );
+        let mut out = Vec::new();
+        write_jsonl(&mut out, &[climate.clone(), climate]).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"city\":\"Oslo\",\"year\":2020,\"temp\":3.4}\n".repeat(2)
+        );
+    }
+
+    #[test]
+    fn test_json_error_messages() {
+        assert_eq!(
+            ParseClimateError::MissingField("temp").to_string(),
+            "missing field \"temp\""
+        );
+        assert_eq!(
+            ParseClimateError::InvalidJson("expected value".into()).to_string(),
+            "invalid JSON record: expected value"
+        );
+    }
+
+    #[test]
+    fn test_from_json() {
+        assert_eq!(
+            Climate::from_json(r#"{"temp":3.4,"year":2020,"city":"Oslo","station":7}"#),
+            Climate::new("Oslo", 2020, 3.4)
+        );
+        assert_eq!(
+            Climate::from_json(r#"{"city":"Oslo","year":2020}"#),
+            Err(ParseClimateError::MissingField("temp"))
+        );
+        assert_eq!(
+            Climate::from_json(r#"{"city":"","year":2020,"temp":3.4}"#),
+            Err(ParseClimateError::NoCity)
+        );
+        assert!(matches!(
+            Climate::from_json(r#"{"city":"Oslo","year":"1999","temp":3.4}"#),
+            Err(ParseClimateError::InvalidJson(_))
+        ));
+        assert!(matches!(
+            Climate::from_json("Oslo,2020,3.4"),
+            Err(ParseClimateError::InvalidJson(_))
+        ));
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let records = vec![
+            Climate::new("The \"Big\" Apple", 2001, -3.5).unwrap(),
+            Climate::new("Oslo", 2020, 3.4)
+                .unwrap()
+                .with_humidity(80.0)
+                .unwrap(),
+        ];
+        let mut out = Vec::new();
+        write_jsonl(&mut out, &records).unwrap();
+        let read: Result<Vec<_>, _> = read_jsonl(&out[..]).collect();
+        assert_eq!(read.unwrap(), records);
+
+        let input = concat!(
+            "{\"city\":\"Oslo\",\"year\":2020,\"temp\":3.4}\n",
+            "\n",
+            "{\"city\":\"Lima\",\"year\":2010}\n",
+            "{\"city\":\"Cairo\",\"year\":2010,\"temp\":22.0}\n",
+        );
+        let results: Vec<_> = read_jsonl(input.as_bytes()).collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(
+            results[0].as_ref().unwrap(),
+            &Climate::new("Oslo", 2020, 3.4).unwrap()
+        );
+        assert!(matches!(
+            results[1],
+            Err(ClimateReadError::Parse {
+                line: 3,
+                error: ParseClimateError::MissingField("temp")
+            })
+        ));
+        assert_eq!(
+            results[2].as_ref().unwrap(),
+            &Climate::new("Cairo", 2010, 22.0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_records_comments() {
+        let input = "# city,year,temp\nHong Kong,1999,25.7\n\n  # measured\nOslo,2020,3.4\n#\n";
+        assert_eq!(
+            parse_records(input),
+            Ok(vec![
+                Climate::new("Hong Kong", 1999, 25.7).unwrap(),
+                Climate::new("Oslo", 2020, 3.4).unwrap(),
+            ])
+        );
+        let input = "# header\n\n# more\nOslo,2020,3.4\nLima,x,1.0\n";
+        let (line, err) = parse_records(input).unwrap_err();
+        assert_eq!(line, 5);
+        assert!(matches!(err, ParseClimateError::ParseInt { .. }));
+        // `#` only starts a comment at the beginning of a line.
+        assert_eq!(
+            parse_records("Oslo,2020,3.4 # warm"),
+            Err((
+                1,
+                ParseClimateError::parse_float(
+                    "3.4 # warm".parse::<f32>().unwrap_err(),
+                    "3.4 # warm"
+                )
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parse_spanned() {
+        let s = "Paris,19x9,25.7";
+        let err = Climate::parse_spanned(s).unwrap_err();
+        assert!(matches!(err.error, ParseClimateError::ParseInt { .. }));
+        assert_eq!(err.span, Some((6, 10)));
+        assert_eq!(&s[6..10], "19x9");
+
+        for (s, span) in [
+            ("Paris,1999,hot", (11, 14)),
+            ("Paris,1999,", (11, 11)),
+            ("\"Paris, France\",1999,-300", (21, 25)),
+            ("Zürich,1999,25.7,120", (18, 21)),
+            ("Paris,1600,25.7", (6, 10)),
+            (",1999,25.7", (0, 0)),
+            ("\"\",1999,25.7", (0, 2)),
+        ] {
+            assert_eq!(
+                Climate::parse_spanned(s).unwrap_err().span,
+                Some(span),
+                "{:?}",
+                s
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_spanned_whole_record() {
+        assert_eq!(
+            Climate::parse_spanned("Paris,1999"),
+            Err(SpannedError {
+                error: ParseClimateError::BadLen {
+                    expected: 3..=4,
+                    found: 2
+                },
+                span: None
+            })
+        );
+        assert_eq!(
+            Climate::parse_spanned("Paris,1999,25.7").ok(),
+            "Paris,1999,25.7".parse().ok()
+        );
+        let err = Climate::parse_spanned("Paris,19x9,25.7").unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("bytes 6..10: error parsing year"));
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn test_fixed_width() {
+        let spec = FixedWidthSpec::default();
+        assert_eq!(
+            Climate::from_fixed_width("Hong Kong           1999  25.7", &spec),
+            Climate::new("Hong Kong", 1999, 25.7)
+        );
+        assert_eq!(
+            Climate::from_fixed_width("São Paulo Zürich    2010  19.2  trailing", &spec),
+            Climate::new("São Paulo Zürich", 2010, 19.2)
+        );
+        let spec = FixedWidthSpec {
+            city: 4..10,
+            year: 0..4,
+            temp: 10..14,
+        };
+        assert_eq!(
+            Climate::from_fixed_width("2001東京    16.4", &spec),
+            Climate::new("東京", 2001, 16.4)
+        );
+    }
+
+    #[test]
+    fn test_fixed_width_errors() {
+        let spec = FixedWidthSpec::default();
+        assert_eq!(
+            Climate::from_fixed_width("Hong Kong           1999  25", &spec),
+            Err(ParseClimateError::BadLen {
+                expected: 3..=3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            Climate::from_fixed_width("", &spec),
+            Err(ParseClimateError::BadLen {
+                expected: 3..=3,
+                found: 0
+            })
+        );
+        assert_eq!(
+            Climate::from_fixed_width("", &spec)
+                .unwrap_err()
+                .to_string(),
+            "incorrect number of fields: expected 3, found 0"
+        );
+        assert_eq!(
+            Climate::from_fixed_width("                    1999  25.7", &spec),
+            Err(ParseClimateError::NoCity)
+        );
+        assert!(matches!(
+            Climate::from_fixed_width("Hong Kong           19x9  25.7", &spec),
+            Err(ParseClimateError::ParseInt { .. })
+        ));
+        assert!(matches!(
+            Climate::from_fixed_width("Zürich              1999  warm", &spec),
+            Err(ParseClimateError::ParseFloat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_default() {
+        let climate = Climate::default();
+        assert_eq!(climate.city, "");
+        assert_eq!(climate.year, 2000);
+        assert_eq!(climate.temp, 0.0);
+        assert_eq!(climate.humidity, None);
+        assert_eq!(
+            Climate::new(climate.city, climate.year, climate.temp),
+            Err(ParseClimateError::NoCity)
+        );
+    }
+
+    #[test]
+    fn test_decimal_comma() {
+        let options = ParseOptions::new(';', NumberFormat::DecimalComma).unwrap();
+        assert_eq!(
+            Climate::parse_with_options("Oslo;2020;3,4", &options),
+            Climate::new("Oslo", 2020, 3.4)
+        );
+        assert_eq!(
+            Climate::parse_with_options("Oslo;2020;-3,4;80,5", &options),
+            Climate::new("Oslo", 2020, -3.4).and_then(|c| c.with_humidity(80.5))
+        );
+        assert_eq!(
+            ClimateParser::with_options(options.clone())
+                .parse_borrowed("Oslo;2020;3,4")
+                .map(ClimateRef::into_owned),
+            Climate::new("Oslo", 2020, 3.4)
+        );
+        assert_eq!(
+            Climate::parse_with_options("Oslo;2020;1.234,5", &options),
+            Err(ParseClimateError::ThousandsSeparator('.'))
+        );
+        assert_eq!(
+            Climate::parse_with_options("Oslo;2020;3.4", &options),
+            Err(ParseClimateError::ThousandsSeparator('.'))
+        );
+    }
+
+    #[test]
+    fn test_decimal_comma_with_comma_delimiter() {
+        assert_eq!(
+            ParseOptions::new(',', NumberFormat::DecimalComma),
+            Err(ParseClimateError::BadDelimiter(','))
+        );
+        let options = ParseOptions {
+            number_format: NumberFormat::DecimalComma,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            Climate::parse_with_options("Oslo,2020,3,4", &options),
+            Err(ParseClimateError::BadDelimiter(','))
+        );
+    }
+
+    #[test]
+    fn test_decimal_point_unchanged() {
+        assert_eq!(
+            ParseOptions::default().number_format,
+            NumberFormat::DecimalPoint
+        );
+        let options = ParseOptions::new(';', NumberFormat::DecimalPoint).unwrap();
+        assert_eq!(
+            Climate::parse_with_options("Oslo;2020;3.4", &options),
+            Climate::new("Oslo", 2020, 3.4)
+        );
+        assert!(matches!(
+            Climate::parse_with_options("Oslo;2020;3,4", &options),
+            Err(ParseClimateError::ParseFloat { .. })
+        ));
+        assert!(matches!(
+            Climate::parse_with_options("Oslo;2020;1,234.5", &options),
+            Err(ParseClimateError::ParseFloat { .. })
+        ));
+    }
+
+    #[test]
+    fn test_line_options() {
+        let input = "\n  \n# header\nOslo,2020,3.4\n; note\n  # indented\nLima,x,1.0\n\n";
+        // Every line counts towards the reported number, skipped or not.
+        assert!(matches!(
+            parse_records("\n  \n# header\nOslo,2020,3.4\n  # indented\nLima,x,1.0\n\n"),
+            Err((6, ParseClimateError::ParseInt { .. }))
+        ));
+
+        let semicolons = LineOptions {
+            comment_prefix: Some(";".to_string()),
+            ..LineOptions::default()
+        };
+        assert!(matches!(
+            parse_records_with(input, &semicolons),
+            Err((3, ParseClimateError::BadLen { found: 1, .. }))
+        ));
+        let both_off = LineOptions {
+            skip_blank_lines: false,
+            comment_prefix: None,
+        };
+        assert_eq!(
+            parse_records_with(input, &both_off),
+            Err((1, ParseClimateError::Empty))
+        );
+
+        assert_eq!(parse_records("# only\n# comments\n\n"), Ok(vec![]));
+        assert_eq!(
+            parse_records_with(
+                "//x\nOslo,2020,3.4",
+                &LineOptions {
+                    comment_prefix: Some("//".to_string()),
+                    ..LineOptions::default()
+                }
+            ),
+            Ok(vec![Climate::new("Oslo", 2020, 3.4).unwrap()])
+        );
+    }
+
+    #[test]
+    fn test_reader_comments() {
+        let input = "# city,year,temp\n\nHong Kong,1999,25.7\n# gap\nOslo,20x0,3.4\n";
+        let results: Vec<_> = ClimateReader::new(input.as_bytes()).collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(
+            results[1],
+            Err(ClimateReadError::Parse { line: 5, .. })
+        ));
+
+        let results: Vec<_> = ClimateReader::new(input.as_bytes())
+            .comment_prefix(None)
+            .collect();
+        assert_eq!(results.len(), 4);
+        assert!(matches!(
+            results[0],
+            Err(ClimateReadError::Parse { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parts_round_trip() {
+        let climate = Climate::new("Hong Kong", 1999, 25.7).unwrap();
+        let parts = climate.clone().into_parts();
+        assert_eq!(parts, ("Hong Kong".to_string(), 1999, 25.7));
+        assert_eq!(Climate::try_from(parts), Ok(climate));
+
+        assert_eq!(
+            Climate::try_from((String::new(), 1999, 25.7)),
+            Err(ParseClimateError::NoCity)
+        );
+    }
+
+    #[test]
+    fn test_parse_with_header() {
+        let expected = vec![
+            Climate::new("Oslo", 2020, 3.4).unwrap(),
+            Climate::new("Lima", 2021, 19.2).unwrap(),
+        ];
+        assert_eq!(
+            parse_with_header("city,year,temp\nOslo,2020,3.4\n\nLima,2021,19.2\n"),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            parse_with_header("\n# reordered\nyear,temp,city\n2020,3.4,Oslo\n2021,19.2,Lima"),
+            Ok(expected)
+        );
+        assert_eq!(
+            parse_with_header("city,year\nOslo,2020\n"),
+            Err(ParseClimateError::BadHeader("city,year".into()))
+        );
+        assert_eq!(
+            parse_with_header("Oslo,2020,3.4\n"),
+            Err(ParseClimateError::BadHeader("Oslo,2020,3.4".into()))
+        );
+        assert_eq!(
+            parse_with_header(""),
+            Err(ParseClimateError::BadHeader(String::new()))
+        );
+        assert_eq!(
+            ParseClimateError::BadHeader("city,year".into()).to_string(),
+            "bad header \"city,year\": expected city, year and temp columns"
+        );
+    }
+
+    #[test]
+    fn test_windows_files() {
+        let expected = vec![
+            Climate::new("Oslo", 2020, 3.4).unwrap(),
+            Climate::new("Lima", 2021, 19.2).unwrap(),
+        ];
+        let bom = "\u{feff}Oslo,2020,3.4\nLima,2021,19.2\n";
+        let crlf = "Oslo,2020,3.4\r\nLima,2021,19.2\r\n";
+        let mixed = "\u{feff}# exported\r\nOslo,2020,3.4\n\r\nLima,2021,19.2\r";
+        for input in [bom, crlf, mixed] {
+            assert_eq!(parse_records(input).as_ref(), Ok(&expected), "{:?}", input);
+            let read: Result<Vec<_>, _> = ClimateReader::new(input.as_bytes()).collect();
+            assert_eq!(read.unwrap(), expected, "{:?}", input);
+        }
+        assert_eq!(
+            parse_with_header("\u{feff}city,year,temp\r\nOslo,2020,3.4\r\n"),
+            Ok(expected[..1].to_vec())
+        );
+
+        assert_eq!(parse_records("\u{feff}"), Ok(vec![]));
+        assert_eq!(ClimateReader::new("\u{feff}".as_bytes()).count(), 0);
+        // Line numbers are unaffected, and a single record stays strict.
+        assert!(matches!(
+            parse_records("\u{feff}Oslo,2020,3.4\r\nLima,x,19.2\r\n"),
+            Err((2, ParseClimateError::ParseInt { .. }))
+        ));
+        let climate: Climate = "\u{feff}Oslo,2020,3.4".parse().unwrap();
+        assert_eq!(climate.city, "\u{feff}Oslo");
+        assert!("Oslo,2020,3.4\r".parse::<Climate>().is_err());
+    }
+
+    #[test]
+    fn test_parse_errors_include_field() {
+        let err = "Paris,19x9,25.7".parse::<Climate>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error parsing year \"19x9\": invalid digit found in string"
+        );
+        let err = "Paris,1999,2x.7".parse::<Climate>().unwrap_err();
+        assert!(err.to_string().contains("\"2x.7\""));
+        let err = "Paris,1999,25.7,dry".parse::<Climate>().unwrap_err();
+        assert!(err.to_string().contains("\"dry\""));
+
+        // Errors compare by both the kind of parse error and the field.
+        let invalid = "x".parse::<u32>().unwrap_err();
+        assert_eq!(
+            ParseClimateError::parse_int(invalid.clone(), "19x9"),
+            ParseClimateError::parse_int(invalid.clone(), "19x9")
+        );
+        assert_ne!(
+            ParseClimateError::parse_int(invalid.clone(), "19x9"),
+            ParseClimateError::parse_int(invalid, "x")
+        );
+        assert_ne!(
+            ParseClimateError::parse_int("".parse::<u32>().unwrap_err(), "x"),
+            ParseClimateError::parse_int("x".parse::<u32>().unwrap_err(), "x")
+        );
+    }
+
+    #[test]
+    fn test_parse_expand_2digit() {
+        let paris = |year| Climate::new("Paris", year, 25.7).unwrap();
+        assert_eq!(
+            Climate::parse_expand_2digit("Paris,99,25.7"),
+            Ok(paris(1999))
+        );
+        assert_eq!(
+            Climate::parse_expand_2digit("Paris,05,25.7"),
+            Ok(paris(2005))
+        );
+        assert_eq!(
+            Climate::parse_expand_2digit("Paris,30,25.7"),
+            Ok(paris(2030))
+        );
+        assert_eq!(
+            Climate::parse_expand_2digit("Paris,31,25.7"),
+            Ok(paris(1931))
+        );
+        assert_eq!(
+            Climate::parse_expand_2digit("Paris,1999,25.7"),
+            Ok(paris(1999))
+        );
+        // Only the digit count matters, not the value.
+        assert_eq!(
+            Climate::parse_expand_2digit("Paris,099,25.7"),
+            Err(ParseClimateError::YearOutOfRange {
+                year: 99,
+                min: Climate::MIN_YEAR,
+                max: Climate::MAX_YEAR
+            })
+        );
+        assert!(matches!(
+            Climate::parse_expand_2digit("Paris,+9,25.7"),
+            Err(ParseClimateError::YearOutOfRange { year: 9, .. })
+        ));
+
+        assert_eq!(
+            "Paris,99,25.7".parse::<Climate>(),
+            Err(ParseClimateError::YearOutOfRange {
+                year: 99,
+                min: Climate::MIN_YEAR,
+                max: Climate::MAX_YEAR
+            })
+        );
+    }
+
+    #[test]
+    fn test_plausible_temp() {
+        for temp in [Climate::MIN_TEMP, 0.0, Climate::MAX_TEMP] {
+            assert!(Climate::new("Vostok", 1983, temp)
+                .unwrap()
+                .is_plausible_temp());
+        }
+        for temp in [-90.1, 60.1, 500.0, -200.0] {
+            assert!(!Climate::new("Vostok", 1983, temp)
+                .unwrap()
+                .is_plausible_temp());
+        }
+
+        assert_eq!(
+            Climate::parse_strict_temp("Vostok,1983,-90"),
+            Climate::new("Vostok", 1983, -90.0)
+        );
+        assert_eq!(
+            Climate::parse_strict_temp("Death Valley,1913,60"),
+            Climate::new("Death Valley", 1913, 60.0)
+        );
+        assert_eq!(
+            Climate::parse_strict_temp("Oslo,2020,500.0"),
+            Err(ParseClimateError::TempOutOfRange(500.0))
+        );
+        assert_eq!(
+            Climate::parse_strict_temp("Oslo,2020,-200.0"),
+            Err(ParseClimateError::TempOutOfRange(-200.0))
+        );
+        assert_eq!(
+            ParseClimateError::TempOutOfRange(500.0).to_string(),
+            "temperature 500 is outside the plausible range -90..=60"
+        );
+        assert!("Oslo,2020,500.0".parse::<Climate>().is_ok());
+    }
+
+    // One error of every variant, each with its kind.
+    fn sample_errors() -> Vec<(ParseClimateError, ParseClimateErrorKind)> {
+        use ParseClimateErrorKind as Kind;
+        let invalid = vec![0xff];
+        vec![
+            (ParseClimateError::Empty, Kind::Empty),
+            (
+                ParseClimateError::BadLen {
+                    expected: 3..=4,
+                    found: 5,
+                },
+                Kind::BadLen,
+            ),
+            (ParseClimateError::NoCity, Kind::NoCity),
+            (
+                ParseClimateError::parse_int("x".parse::<u32>().unwrap_err(), "x"),
+                Kind::ParseInt,
+            ),
+            (
+                ParseClimateError::parse_float("x".parse::<f32>().unwrap_err(), "x"),
+                Kind::ParseFloat,
+            ),
+            (
+                ParseClimateError::parse_humidity("x".parse::<f32>().unwrap_err(), "x"),
+                Kind::ParseHumidity,
+            ),
+            (ParseClimateError::BadDelimiter('.'), Kind::BadDelimiter),
+            (
+                ParseClimateError::UnterminatedQuote,
+                Kind::UnterminatedQuote,
+            ),
+            (ParseClimateError::NotFinite, Kind::NotFinite),
+            (
+                ParseClimateError::YearOutOfRange {
+                    year: 99,
+                    min: Climate::MIN_YEAR,
+                    max: Climate::MAX_YEAR,
+                },
+                Kind::YearOutOfRange,
+            ),
+            (ParseClimateError::UnknownUnit('X'), Kind::UnknownUnit),
+            (
+                ParseClimateError::BelowAbsoluteZero,
+                Kind::BelowAbsoluteZero,
+            ),
+            (ParseClimateError::MissingYear, Kind::MissingYear),
+            (ParseClimateError::MissingTemp, Kind::MissingTemp),
+            (
+                ParseClimateError::UnknownColumn("x".into()),
+                Kind::UnknownColumn,
+            ),
+            (
+                ParseClimateError::MissingColumn("temp"),
+                Kind::MissingColumn,
+            ),
+            (
+                ParseClimateError::HumidityOutOfRange(101.0),
+                Kind::HumidityOutOfRange,
+            ),
+            (
+                ParseClimateError::InvalidUtf8(std::str::from_utf8(&invalid).unwrap_err()),
+                Kind::InvalidUtf8,
+            ),
+            (ParseClimateError::MissingField("city"), Kind::MissingField),
+            (
+                ParseClimateError::InvalidJson("x".into()),
+                Kind::InvalidJson,
+            ),
+            (
+                ParseClimateError::ThousandsSeparator('.'),
+                Kind::ThousandsSeparator,
+            ),
+            (ParseClimateError::BadHeader("x".into()), Kind::BadHeader),
+            (
+                ParseClimateError::TempOutOfRange(500.0),
+                Kind::TempOutOfRange,
+            ),
+            (
+                ParseClimateError::NonCanonicalYear("+1999".into()),
+                Kind::NonCanonicalYear,
+            ),
+            (
+                ParseClimateError::NonCanonicalTemp("+2.5".into()),
+                Kind::NonCanonicalTemp,
+            ),
+        ]
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        use ParseClimateErrorKind as Kind;
+        for (error, kind) in &sample_errors() {
+            assert_eq!(error.kind(), *kind);
+            assert_eq!(error.clone(), *error);
+            let message = error.to_string();
+            assert!(!message.is_empty());
+            assert!(!message.contains("unhandled error!"), "{}", message);
+        }
+        assert_eq!(
+            "Paris,19x9,25.7".parse::<Climate>().unwrap_err().kind(),
+            Kind::ParseInt
+        );
+    }
+
+    #[test]
+    fn test_climate_set() {
+        let input =
+            "Oslo,2020,3.4\nLima,2021,19.2\nbad\nCairo,2019,35.1\nOslo,2018,35.1\nNuuk,2020,-1.5\n";
+        let set: ClimateSet = records_iter(input).filter_map(Result::ok).collect();
+        assert_eq!(set.count(), 5);
+        let avg = set.avg_temp().unwrap();
+        assert!((avg - 18.26).abs() < 1e-4, "{}", avg);
+        // The earlier year wins a tie.
+        assert_eq!(
+            set.warmest(),
+            Some(&Climate::new("Oslo", 2018, 35.1).unwrap())
+        );
+        assert_eq!(
+            set.coldest(),
+            Some(&Climate::new("Nuuk", 2020, -1.5).unwrap())
+        );
+
+        let records: Vec<Climate> = records_iter(input).filter_map(Result::ok).collect();
+        assert_eq!(set.warmest(), warmest(&records));
+        assert_eq!(set.coldest(), coldest(&records));
+
+        let empty = ClimateSet::new();
+        assert_eq!(empty.count(), 0);
+        assert_eq!(empty.avg_temp(), None);
+        assert_eq!(empty.warmest(), None);
+        assert_eq!(empty.coldest(), None);
+    }
+
+    #[test]
+    fn test_canonical_numbers() {
+        let strict = ParseOptions {
+            canonical_numbers: true,
+            ..ParseOptions::default()
+        };
+        let parse = |s| Climate::parse_with_options(s, &strict);
+        assert_eq!(
+            parse("Oslo,+1999,2.5"),
+            Err(ParseClimateError::NonCanonicalYear("+1999".into()))
+        );
+        assert_eq!(
+            parse("Oslo,01999,2.5"),
+            Err(ParseClimateError::NonCanonicalYear("01999".into()))
+        );
+        assert_eq!(
+            parse("Oslo,1999,+2.5"),
+            Err(ParseClimateError::NonCanonicalTemp("+2.5".into()))
+        );
+        assert_eq!(parse("Oslo,1999,-2.5"), Climate::new("Oslo", 1999, -2.5));
+        assert_eq!(parse("Oslo,1999,0.5"), Climate::new("Oslo", 1999, 0.5));
+        assert_eq!(
+            ClimateParser::with_options(strict.clone())
+                .parse_borrowed("Oslo,+1999,2.5")
+                .map(ClimateRef::into_owned),
+            Err(ParseClimateError::NonCanonicalYear("+1999".into()))
+        );
+
+        // Off by default.
+        assert_eq!("Oslo,+1999,+2.5".parse(), Climate::new("Oslo", 1999, 2.5));
+        assert_eq!("Oslo,01999,2.5".parse(), Climate::new("Oslo", 1999, 2.5));
+    }
+
+    #[test]
+    fn test_try_from_str_tuple() {
+        assert_eq!(
+            Climate::try_from(("Hong Kong", "1999", "25.7")),
+            Climate::new("Hong Kong", 1999, 25.7)
+        );
+        assert_eq!(
+            Climate::try_from(("", "1999", "25.7")),
+            Err(ParseClimateError::NoCity)
+        );
+        assert!(matches!(
+            Climate::try_from(("Hong Kong", "19x9", "25.7")),
+            Err(ParseClimateError::ParseInt { .. })
+        ));
+        assert_eq!(
+            Climate::try_from(("Hong Kong", "1999", "inf")),
+            Err(ParseClimateError::NotFinite)
+        );
+        assert_eq!(
+            Climate::new("Hong Kong", 1999, f32::NAN),
+            Err(ParseClimateError::NotFinite)
+        );
+    }
+
+    #[test]
+    fn test_validate_all() {
+        let input =
+            "Oslo,2020,3.4\n,2020,3.4\nLima,2021,19.2\nCairo,19x9,35.1\n# note\nNuuk,2020,-300\n";
+        let errors = validate_all(input);
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0], (2, ParseClimateError::NoCity));
+        assert!(matches!(errors[1], (4, ParseClimateError::ParseInt { .. })));
+        assert_eq!(errors[2], (6, ParseClimateError::BelowAbsoluteZero));
+
+        assert_eq!(validate_all("Oslo,2020,3.4\n\nLima,2021,19.2\n"), vec![]);
+        assert_eq!(validate_all(""), vec![]);
+    }
+
+    #[test]
+    fn test_is_recoverable() {
+        use ParseClimateErrorKind as Kind;
+        for (error, kind) in &sample_errors() {
+            let structural = matches!(
+                kind,
+                Kind::Empty
+                    | Kind::BadLen
+                    | Kind::BadDelimiter
+                    | Kind::UnknownColumn
+                    | Kind::MissingColumn
+                    | Kind::BadHeader
+                    | Kind::InvalidUtf8
+                    | Kind::InvalidJson
+            );
+            assert_eq!(error.is_recoverable(), !structural, "{:?}", error);
+        }
+        assert!(!"".parse::<Climate>().unwrap_err().is_recoverable());
+        assert!("Paris,19x9,25.7"
+            .parse::<Climate>()
+            .unwrap_err()
+            .is_recoverable());
+    }
+
+    #[test]
+    fn test_builder_from_text() {
+        let built = Climate::builder()
+            .city("Oslo")
+            .year_str("2020")
+            .temp_str("38.1F")
+            .humidity_str("80")
+            .build()
+            .unwrap();
+        assert_eq!(built.year, 2020);
+        assert!((built.temp - 3.388_889).abs() < 1e-4);
+        assert_eq!(built.humidity, Some(80.0));
+
+        // Every bad field is reported, in field order.
+        let errors = Climate::builder()
+            .city("")
+            .year_str("19x9")
+            .temp_str("warm")
+            .build()
+            .unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0], ParseClimateError::NoCity);
+        assert!(matches!(&errors[1], ParseClimateError::ParseInt { field, .. } if field == "19x9"));
+        assert!(
+            matches!(&errors[2], ParseClimateError::ParseFloat { field, .. } if field == "warm")
+        );
+
+        assert_eq!(
+            Climate::builder().city("Oslo").year_str("2020").build(),
+            Err(vec![ParseClimateError::MissingField("temp")])
+        );
+        assert_eq!(
+            Climate::builder()
+                .city("Oslo")
+                .year_str("")
+                .year_str("1")
+                .temp(3.4)
+                .build(),
+            Err(vec![ParseClimateError::YearOutOfRange {
+                year: 1,
+                min: Climate::MIN_YEAR,
+                max: Climate::MAX_YEAR
+            }])
+        );
+        // A later setter replaces an earlier one, bad or not.
+        assert_eq!(
+            Climate::builder()
+                .city("")
+                .city("Oslo")
+                .year_str("20x0")
+                .year(2020)
+                .temp(3.4)
+                .build(),
+            Climate::new("Oslo", 2020, 3.4).map_err(|e| vec![e])
+        );
+    }
+
+    #[test]
+    fn test_dataset_filters() {
+        let mut dataset = sample_dataset();
+        dataset.insert(Climate::new("Cairo", 1998, 35.1).unwrap());
+        dataset.insert(Climate::new("Cairo", 2001, 36.4).unwrap());
+        let keys = |records: Vec<&Climate>| -> Vec<(String, u32)> {
+            records.iter().map(|r| (r.city.clone(), r.year)).collect()
+        };
+        let key = |city: &str, year| (city.to_string(), year);
+
+        assert_eq!(
+            keys(dataset.filter_years(1995..=1999).collect()),
+            [
+                key("Cairo", 1998),
+                key("Hong Kong", 1995),
+                key("Hong Kong", 1999)
+            ]
+        );
+        assert_eq!(
+            keys(dataset.filter_years(..2000).collect()),
+            [
+                key("Cairo", 1998),
+                key("Hong Kong", 1995),
+                key("Hong Kong", 1999),
+                key("Oslo", 1990)
+            ]
+        );
+        assert_eq!(
+            keys(dataset.filter_years(2001..).collect()),
+            [key("Cairo", 2001), key("Oslo", 2005), key("Oslo", 2020)]
+        );
+        assert_eq!(dataset.filter_years(..).count(), dataset.len());
+        assert_eq!(dataset.filter_years(1999..1999).count(), 0);
+
+        assert_eq!(
+            keys(dataset.filter_temp(30.0..).collect()),
+            [key("Cairo", 1998), key("Cairo", 2001)]
+        );
+        assert_eq!(
+            keys(dataset.filter_temp(..=2.8).collect()),
+            [key("Oslo", 1990), key("Oslo", 2005)]
+        );
+        assert_eq!(
+            keys(dataset.filter_temp(2.8..25.7).collect()),
+            [key("Hong Kong", 1995), key("Oslo", 2005), key("Oslo", 2020)]
+        );
+
+        assert_eq!(
+            keys(dataset.filter_city("Oslo").collect()),
+            [key("Oslo", 1990), key("Oslo", 2005), key("Oslo", 2020)]
+        );
+        assert_eq!(dataset.filter_city("oslo").count(), 0);
+
+        let hot_nineties: Vec<_> = dataset
+            .filter_years(1990..=2000)
+            .filter(|r| r.temp > 30.0)
+            .collect();
+        assert_eq!(keys(hot_nineties), [key("Cairo", 1998)]);
+    }
+
+    #[test]
+    fn test_same_observation() {
+        let shouted = Climate::new(" PARIS ", 1999, 25.7).unwrap();
+        let quiet = Climate::new("paris", 1999, 24.1).unwrap();
+        assert!(shouted.same_observation(&quiet));
+        assert!(quiet.same_observation(&shouted));
+        assert!(Climate::new("new  york", 1999, 1.0)
+            .unwrap()
+            .same_observation(&Climate::new("New York", 1999, 2.0).unwrap()));
+
+        assert!(!shouted.same_observation(&Climate::new("paris", 2000, 25.7).unwrap()));
+        assert!(!shouted.same_observation(&Climate::new("Parish", 1999, 25.7).unwrap()));
+        assert_ne!(shouted, quiet);
+    }
+
+    #[test]
+    fn test_stats_by_city() {
+        let dataset: ClimateDataset = [
+            Climate::new("Oslo", 2020, 3.0).unwrap(),
+            Climate::new("Oslo", 2021, -8.1).unwrap(),
+            Climate::new("Oslo", 2022, 19.2).unwrap(),
+            Climate::new("Lima", 2020, 19.0).unwrap(),
+            Climate::new("Cairo", 2020, 35.5).unwrap(),
+            Climate::new("Cairo", 2021, 34.5).unwrap(),
+        ]
+        .into_iter()
+        .collect();
+        let stats = dataset.stats_by_city();
+        assert_eq!(stats.keys().collect::<Vec<_>>(), ["Cairo", "Lima", "Oslo"]);
+        assert_eq!(stats["Cairo"].count(), 2);
+        assert_eq!(stats["Cairo"].mean_temp(), Some(35.0));
+        // A single record gives a mean, not NaN.
+        assert_eq!(stats["Lima"].mean_temp(), Some(19.0));
+        assert_eq!(stats["Lima"].min_temp(), Some(19.0));
+        assert_eq!(stats["Oslo"].min_temp(), Some(-8.1));
+        assert_eq!(stats["Oslo"].max_temp(), Some(19.2));
+        assert!((stats["Oslo"].mean_temp().unwrap() - 14.1 / 3.0).abs() < 1e-5);
+
+        assert_eq!(dataset.warmest_city(), Some(("Cairo", 35.0)));
+        assert_eq!(dataset.coldest_city().map(|(city, _)| city), Some("Oslo"));
+        assert_eq!(
+            dataset.report(),
+            "Cairo: n=2 mean=35.0 min=34.5 max=35.5\n\
+             Lima: n=1 mean=19.0 min=19.0 max=19.0\n\
+             Oslo: n=3 mean=4.7 min=-8.1 max=19.2\n"
+        );
+
+        // Ties go to the first city by name.
+        let tied: ClimateDataset = [
+            Climate::new("Oslo", 2020, 5.0).unwrap(),
+            Climate::new("Bergen", 2020, 5.0).unwrap(),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(tied.warmest_city(), Some(("Bergen", 5.0)));
+        assert_eq!(tied.coldest_city(), Some(("Bergen", 5.0)));
+
+        let empty = ClimateDataset::new();
+        assert!(empty.stats_by_city().is_empty());
+        assert_eq!(empty.warmest_city(), None);
+        assert_eq!(empty.report(), "");
+    }
+
+    #[test]
+    fn test_table_row() {
+        let header = climate_table_header();
+        assert_eq!(header, "city                year  temp");
+
+        let row = Climate::new("Oslo", 2020, 3.45).unwrap().to_table_row();
+        assert_eq!(row, "Oslo                2020   3.5");
+        assert_eq!(row.chars().count(), header.chars().count());
+        assert_eq!(
+            Climate::from_fixed_width(&row, &FixedWidthSpec::default()),
+            Climate::new("Oslo", 2020, 3.5)
+        );
+
+        let long = Climate::new("Llanfairpwllgwyngyllgogerych", 1999, -12.0).unwrap();
+        assert_eq!(long.to_table_row(), "Llanfairpwllgwyngyll1999 -12.0");
+        let accented = Climate::new("São Tomé e Príncipe, capital", 2001, 26.1).unwrap();
+        let row = accented.to_table_row();
+        assert_eq!(row, "São Tomé e Príncipe,2001  26.1");
+        assert_eq!(row.chars().count(), 30);
+    }
+
+    #[test]
+    fn test_top_n_by_temp() {
+        let mut dataset = sample_dataset();
+        for record in [
+            Climate::new("Cairo", 1998, 35.1).unwrap(),
+            Climate::new("Cairo", 2001, 25.7).unwrap(),
+            Climate::new("Athens", 2001, 25.7).unwrap(),
+            Climate::new("Oslo", 2006, 2.8).unwrap(),
+        ] {
+            dataset.insert(record);
+        }
+        let key = |r: &&Climate| (r.city.clone(), r.year);
+        let keys = |records: Vec<&Climate>| records.iter().map(key).collect::<Vec<_>>();
+
+        assert_eq!(
+            keys(dataset.top_n_by_temp(4)),
+            [
+                ("Cairo".to_string(), 1998),
+                ("Athens".to_string(), 2001),
+                ("Cairo".to_string(), 2001),
+                ("Hong Kong".to_string(), 1999),
+            ]
+        );
+        assert_eq!(
+            keys(dataset.bottom_n_by_temp(3)),
+            [
+                ("Oslo".to_string(), 1990),
+                ("Oslo".to_string(), 2005),
+                ("Oslo".to_string(), 2006),
+            ]
+        );
+        assert!(dataset.top_n_by_temp(0).is_empty());
+        assert!(dataset.bottom_n_by_temp(0).is_empty());
+
+        // Checked against a full sort for every n, including more than the
+        // dataset holds.
+        let mut sorted: Vec<&Climate> = dataset.sort_by_temp();
+        for n in 0..=dataset.len() + 2 {
+            let coldest: Vec<_> = sorted.iter().copied().take(n).collect();
+            assert_eq!(dataset.bottom_n_by_temp(n), coldest);
+        }
+        sorted.sort_by(|a, b| b.temp.total_cmp(&a.temp).then_with(|| a.cmp(b)));
+        for n in 0..=dataset.len() + 2 {
+            let warmest: Vec<_> = sorted.iter().copied().take(n).collect();
+            assert_eq!(dataset.top_n_by_temp(n), warmest);
+        }
+
+        assert_eq!(
+            keys(dataset.top_n_by_temp_for("Oslo", 2)),
+            [("Oslo".to_string(), 2020), ("Oslo".to_string(), 2005)]
+        );
+        assert_eq!(dataset.bottom_n_by_temp_for("Oslo", 10).len(), 4);
+        assert!(dataset.top_n_by_temp_for("Lima", 3).is_empty());
+    }
+
+    #[test]
+    fn test_median_temp() {
+        let records = |temps: &[f32]| -> Vec<Climate> {
+            temps
+                .iter()
+                .map(|&t| Climate::new("Oslo", 2020, t).unwrap())
+                .collect()
+        };
+        assert_eq!(median_temp(&records(&[9.0, -3.0, 4.5])), Some(4.5));
+        assert_eq!(
+            median_temp(&records(&[100.0, 1.0, 2.0, 3.0, 4.0])),
+            Some(3.0)
+        );
+        assert_eq!(median_temp(&records(&[4.0, 1.0, 3.0, 2.0])), Some(2.5));
+        assert_eq!(median_temp(&records(&[7.0])), Some(7.0));
+        assert_eq!(median_temp(&[]), None);
+    }
+
+    fn distribution(temps: &[f32]) -> ClimateDistribution {
+        temps
+            .iter()
+            .map(|&t| Climate::new("Oslo", 2020, t).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_distribution_odd() {
+        let dist = distribution(&[5.0, -1.0, 3.0, 9.0, 1.0]);
+        assert_eq!(dist.len(), 5);
+        assert_eq!(dist.percentile(0.0), Some(-1.0));
+        assert_eq!(dist.percentile(1.0), Some(9.0));
+        assert_eq!(dist.median(), Some(3.0));
+        assert_eq!(dist.percentile(0.25), Some(1.0));
+        assert_eq!(dist.percentile(0.75), Some(5.0));
+        // Rank 0.1 * 4 = 0.4, between -1 and 1.
+        assert!((dist.percentile(0.1).unwrap() - -0.2).abs() < 1e-6);
+        assert_eq!(dist.interquartile_range(), Some(4.0));
+    }
+
+    #[test]
+    fn test_distribution_even() {
+        let dist = distribution(&[4.0, 1.0, 3.0, 2.0]);
+        assert_eq!(dist.percentile(0.0), Some(1.0));
+        assert_eq!(dist.percentile(1.0), Some(4.0));
+        assert_eq!(dist.median(), Some(2.5));
+        // Rank 0.25 * 3 = 0.75 and 0.75 * 3 = 2.25.
+        assert_eq!(dist.percentile(0.25), Some(1.75));
+        assert_eq!(dist.percentile(0.75), Some(3.25));
+        assert_eq!(dist.interquartile_range(), Some(1.5));
+        let records: Vec<_> = [4.0, 1.0, 3.0, 2.0]
+            .iter()
+            .map(|&t| Climate::new("Oslo", 2020, t).unwrap())
+            .collect();
+        assert_eq!(dist.median(), median_temp(&records));
+    }
+
+    #[test]
+    fn test_distribution_empty() {
+        let dist = distribution(&[]);
+        assert!(dist.is_empty());
+        assert_eq!(dist.median(), None);
+        assert_eq!(dist.percentile(0.9), None);
+        assert_eq!(dist.interquartile_range(), None);
+        assert_eq!(distribution(&[7.0]).percentile(0.3), Some(7.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "percentile must be within 0.0..=1.0, got 1.5")]
+    fn test_distribution_percentile_out_of_range() {
+        distribution(&[1.0, 2.0]).percentile(1.5);
+    }
+
+    #[test]
+    fn test_temp_histogram() {
+        let records: Vec<_> = [-7.5, -0.1, 0.0, 3.2, 4.9, 5.0, 12.0, 14.9]
+            .iter()
+            .map(|&t| Climate::new("Oslo", 2020, t).unwrap())
+            .collect();
+        let histogram = temp_histogram(&records, 5.0);
+        assert_eq!(
+            histogram.into_iter().collect::<Vec<_>>(),
+            [(-2, 1), (-1, 1), (0, 3), (1, 1), (2, 2)]
+        );
+        assert!(temp_histogram(&[], 5.0).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "bucket width must be positive, got 0")]
+    fn test_temp_histogram_zero_width() {
+        temp_histogram(&[], 0.0);
+    }
+
+    #[test]
+    fn test_trend_for() {
+        let mut dataset: ClimateDataset = (0..5)
+            .map(|i| Climate::new("Oslo", 1990 + 5 * i, 2.0 + 0.1 * i as f32).unwrap())
+            .collect();
+        let trend = dataset.trend_for("Oslo").unwrap();
+        assert!((trend.slope_per_year - 0.02).abs() < 1e-6, "{:?}", trend);
+        assert!((trend.slope_per_decade() - 0.2).abs() < 1e-5);
+        assert!((trend.intercept + 0.02 * 1990.0 - 2.0).abs() < 1e-3);
+        assert!((trend.r_squared - 1.0).abs() < 1e-9);
+
+        for year in [2000, 2010, 2020] {
+            dataset.insert(Climate::new("Lima", year, 19.0).unwrap());
+        }
+        let flat = dataset.trend_for("Lima").unwrap();
+        assert_eq!(flat.slope_per_year, 0.0);
+        assert!((flat.intercept - 19.0).abs() < 1e-9);
+        assert_eq!(flat.r_squared, 1.0);
+
+        dataset.insert(Climate::new("Cairo", 2000, 35.0).unwrap());
+        assert_eq!(dataset.trend_for("Cairo"), None);
+        dataset.insert(Climate::new("Cairo", 2000, 36.0).unwrap());
+        assert_eq!(dataset.trend_for("Cairo"), None);
+        assert_eq!(dataset.trend_for("Nuuk"), None);
+
+        // A noisy series explains less than all of its variance.
+        let noisy = Trend::fit(&[
+            Climate::new("Nuuk", 2000, 1.0).unwrap(),
+            Climate::new("Nuuk", 2001, 3.0).unwrap(),
+            Climate::new("Nuuk", 2002, 2.0).unwrap(),
+        ])
+        .unwrap();
+        assert!((noisy.slope_per_year - 0.5).abs() < 1e-9);
+        assert!((noisy.r_squared - 0.25).abs() < 1e-9);
+    }
+
+    // `anyhow::Error` and other error reporters need `Send + Sync + 'static`.
+    #[test]
+    fn test_send_sync_question_mark() {
+        fn parse(s: &str) -> Result<Climate, Box<dyn Error + Send + Sync>> {
+            Ok(s.parse::<Climate>()?)
+        }
+        assert_eq!(
+            parse("Oslo,2020,3.4").unwrap(),
+            Climate::new("Oslo", 2020, 3.4).unwrap()
+        );
+        let err = parse("Paris,19x9,25.7").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error parsing year \"19x9\": invalid digit found in string"
+        );
+        assert!(matches!(
+            err.downcast_ref::<ParseClimateError>(),
+            Some(ParseClimateError::ParseInt { .. })
+        ));
+        assert!(err.source().is_some_and(|e| e.source().is_none()));
+    }
+
+    #[test]
+    fn test_moving_average() {
+        let dataset: ClimateDataset = [(2000, 1.0), (2001, 2.0), (2002, 6.0), (2003, 4.0)]
+            .iter()
+            .map(|&(year, temp)| Climate::new("Oslo", year, temp).unwrap())
+            .collect();
+        for gaps in [GapPolicy::SkipMissing, GapPolicy::Strict] {
+            assert_eq!(
+                dataset.moving_average("Oslo", 3, gaps).unwrap(),
+                [
+                    (2000, None),
+                    (2001, Some(3.0)),
+                    (2002, Some(4.0)),
+                    (2003, None)
+                ]
+            );
+            assert_eq!(
+                dataset.moving_average("Oslo", 1, gaps).unwrap(),
+                [
+                    (2000, Some(1.0)),
+                    (2001, Some(2.0)),
+                    (2002, Some(6.0)),
+                    (2003, Some(4.0))
+                ]
+            );
+            assert_eq!(
+                dataset.moving_average("Oslo", 2, gaps).unwrap(),
+                [
+                    (2000, Some(1.5)),
+                    (2001, Some(4.0)),
+                    (2002, Some(5.0)),
+                    (2003, None)
+                ]
+            );
+            assert_eq!(
+                dataset.moving_average("Oslo", 5, gaps).unwrap()[2],
+                (2002, None)
+            );
+            assert!(dataset.moving_average("Lima", 3, gaps).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_moving_average_gaps() {
+        let dataset: ClimateDataset = [
+            (2000, 1.0),
+            (2001, 2.0),
+            (2004, 6.0),
+            (2005, 4.0),
+            (2005, 8.0),
+        ]
+        .iter()
+        .map(|&(year, temp)| Climate::new("Oslo", year, temp).unwrap())
+        .collect();
+        assert_eq!(
+            dataset
+                .moving_average("Oslo", 3, GapPolicy::SkipMissing)
+                .unwrap(),
+            [
+                (2000, None),
+                (2001, Some(3.0)),
+                (2004, Some(14.0 / 3.0)),
+                (2005, None)
+            ]
+        );
+        assert_eq!(
+            dataset
+                .moving_average("Oslo", 3, GapPolicy::Strict)
+                .unwrap(),
+            [(2000, None), (2001, None), (2004, None), (2005, None)]
+        );
+        assert_eq!(
+            dataset
+                .moving_average("Oslo", 2, GapPolicy::Strict)
+                .unwrap(),
+            [
+                (2000, Some(1.5)),
+                (2001, None),
+                (2004, Some(6.0)),
+                (2005, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_moving_average_zero_window() {
+        for gaps in [GapPolicy::SkipMissing, GapPolicy::Strict] {
+            assert_eq!(sample_dataset().moving_average("Oslo", 0, gaps), None);
+            assert_eq!(sample_dataset().moving_average("Lima", 0, gaps), None);
+        }
+    }
+
+    #[test]
+    fn test_with_year_and_temp() {
+        let original = Climate::new("Oslo", 2020, 3.4).unwrap();
+        let updated = original
+            .clone()
+            .with_year(2021)
+            .unwrap()
+            .with_temp(4.1)
+            .unwrap();
+        assert_eq!(updated, Climate::new("Oslo", 2021, 4.1).unwrap());
+        assert_eq!(original, Climate::new("Oslo", 2020, 3.4).unwrap());
+
+        assert_eq!(
+            original.clone().with_year(1066),
+            Err(ParseClimateError::YearOutOfRange {
+                year: 1066,
+                min: Climate::MIN_YEAR,
+                max: Climate::MAX_YEAR
+            })
+        );
+        assert_eq!(
+            original.clone().with_temp(f32::INFINITY),
+            Err(ParseClimateError::NotFinite)
+        );
+        assert_eq!(
+            original.with_temp(-300.0),
+            Err(ParseClimateError::BelowAbsoluteZero)
+        );
+    }
+
+    #[test]
+    fn test_anomalies() {
+        let mut dataset = sample_dataset();
+        let oslo = dataset.anomalies("Oslo", 1961..=2010).unwrap();
+        // The baseline mean is (2.1 + 2.8) / 2 = 2.45.
+        let expected = [(1990, -0.35), (2005, 0.35), (2020, 0.95)];
+        assert_eq!(oslo.len(), expected.len());
+        for (&(year, anomaly), &(expected_year, expected_anomaly)) in oslo.iter().zip(&expected) {
+            assert_eq!(year, expected_year);
+            assert!(
+                (anomaly - expected_anomaly).abs() < 1e-5,
+                "{} {}",
+                year,
+                anomaly
+            );
+        }
+        let baseline_sum: f32 = oslo
+            .iter()
+            .filter(|(year, _)| (1961..=2010).contains(year))
+            .map(|(_, anomaly)| anomaly)
+            .sum();
+        assert!(baseline_sum.abs() < 1e-5);
+
+        assert_eq!(
+            dataset.anomalies("Lima", 1961..=1990),
+            Err(AnomalyError::UnknownCity("Lima".into()))
+        );
+        assert_eq!(
+            dataset.anomalies("Hong Kong", 1961..=1990),
+            Err(AnomalyError::EmptyBaseline("Hong Kong".into()))
+        );
+        assert_eq!(
+            AnomalyError::EmptyBaseline("Hong Kong".into()).to_string(),
+            "no records for \"Hong Kong\" in the baseline years"
+        );
+
+        dataset.insert(Climate::new("Hong Kong", 2000, 25.3).unwrap());
+        let all = dataset.anomalies_by_city(1995..=1999);
+        assert_eq!(all.keys().collect::<Vec<_>>(), ["Hong Kong"]);
+        let sum: f32 = all["Hong Kong"][..2].iter().map(|(_, a)| a).sum();
+        assert!(sum.abs() < 1e-5);
+        assert_eq!(all["Hong Kong"].len(), 3);
+    }
+
+    #[test]
+    fn test_outliers() {
+        let mut dataset: ClimateDataset = (2000..2009)
+            .map(|year| Climate::new("Cairo", year, 20.0 + (year % 3) as f32).unwrap())
+            .collect();
+        dataset.insert(Climate::new("Cairo", 2009, 250.0).unwrap());
+        // Exactly one standard deviation from the mean: not an outlier.
+        for (year, temp) in [(2000, -1.0), (2001, 0.0), (2002, 1.0)] {
+            dataset.insert(Climate::new("Nuuk", year, temp).unwrap());
+        }
+        // Too few records to judge.
+        dataset.insert(Climate::new("Lima", 2000, 19.0).unwrap());
+        dataset.insert(Climate::new("Lima", 2001, 90.0).unwrap());
+
+        assert_eq!(
+            dataset.outliers(2.0),
+            [&Climate::new("Cairo", 2009, 250.0).unwrap()]
+        );
+        assert_eq!(
+            dataset.outliers(1.0).last().map(|r| r.city.as_str()),
+            Some("Cairo")
+        );
+        assert!(!dataset.outliers(1.0).iter().any(|r| r.city == "Nuuk"));
+        assert_eq!(
+            dataset
+                .outliers(0.99)
+                .iter()
+                .filter(|r| r.city == "Nuuk")
+                .count(),
+            2
+        );
+        assert!(!dataset.outliers(0.0).iter().any(|r| r.city == "Lima"));
+
+        let len = dataset.len();
+        dataset.retain_inliers(2.0);
+        assert_eq!(dataset.len(), len - 1);
+        assert_eq!(dataset.years_for("Cairo"), (2000..2009).collect::<Vec<_>>());
+        assert!(dataset.outliers(2.0).is_empty());
+    }
+
+    #[test]
+    fn test_parse_euro_locale() {
+        assert_eq!(
+            Climate::parse_euro_locale("Berlin;2015;7,3"),
+            Climate::new("Berlin", 2015, 7.3)
+        );
+        assert_eq!(
+            Climate::parse_euro_locale("Berlin;2015;-7,3;81,5"),
+            Climate::new("Berlin", 2015, -7.3).and_then(|c| c.with_humidity(81.5))
+        );
+        assert!(matches!(
+            Climate::parse_euro_locale("Berlin;2015;7,3,1"),
+            Err(ParseClimateError::ParseFloat { field, .. }) if field == "7,3,1"
+        ));
+        assert_eq!(
+            Climate::parse_euro_locale("Berlin,2015,7.3"),
+            Err(ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let records = [
+            Climate::new(" Paris ", 1999, 25.7).unwrap(),
+            Climate::new("Paris", 2000, 25.7).unwrap(),
+            Climate::new("paris", 1999, 24.1).unwrap(),
+            Climate::new("Oslo", 1999, 3.4).unwrap(),
+        ];
+        assert_eq!(find_duplicates(&records), [(0, 2)]);
+
+        let mut more = records.to_vec();
+        more.push(Climate::new("PARIS", 1999, 26.0).unwrap());
+        more.push(Climate::new("Oslo", 1999, 3.4).unwrap());
+        assert_eq!(find_duplicates(&more), [(0, 2), (0, 4), (2, 4), (3, 5)]);
+        assert!(find_duplicates(&records[..2]).is_empty());
+        assert!(find_duplicates(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let mut dataset: ClimateDataset = [(1990, 2.0), (1994, 4.0), (1995, 3.0), (1995, 5.0)]
+            .iter()
+            .map(|&(year, temp)| Climate::new("Oslo", year, temp).unwrap())
+            .collect();
+        dataset.insert(Climate::new("Lima", 2000, 19.0).unwrap());
+
+        assert_eq!(dataset.interpolate("Oslo", 1990), Some(2.0));
+        assert_eq!(dataset.interpolate("Oslo", 1991), Some(2.5));
+        assert_eq!(dataset.interpolate("Oslo", 1993), Some(3.5));
+        // Records from the same year are averaged.
+        assert_eq!(dataset.interpolate("Oslo", 1995), Some(4.0));
+        assert_eq!(dataset.interpolate("Oslo", 1989), None);
+        assert_eq!(dataset.interpolate("Oslo", 1996), None);
+        assert_eq!(dataset.interpolate("Lima", 2000), Some(19.0));
+        assert_eq!(dataset.interpolate("Lima", 2001), None);
+        assert_eq!(dataset.interpolate("Nuuk", 2000), None);
+    }
+
+    #[test]
+    fn test_fill_gaps() {
+        let dataset: ClimateDataset = [(1990, 2.0), (1994, 4.0), (1995, 3.0)]
+            .iter()
+            .map(|&(year, temp)| Climate::new("Oslo", year, temp).unwrap())
+            .collect();
+        let filled: Vec<(u32, f32, bool)> = dataset
+            .fill_gaps("Oslo")
+            .into_iter()
+            .map(|(r, interpolated)| (r.year, r.temp, interpolated))
+            .collect();
+        assert_eq!(
+            filled,
+            [
+                (1990, 2.0, false),
+                (1991, 2.5, true),
+                (1992, 3.0, true),
+                (1993, 3.5, true),
+                (1994, 4.0, false),
+                (1995, 3.0, false),
+            ]
+        );
+        assert_eq!(
+            sample_dataset().fill_gaps("Hong Kong").len(),
+            5,
+            "1995 to 1999"
+        );
+        assert!(dataset.fill_gaps("Nuuk").is_empty());
+        let single: ClimateDataset = [Climate::new("Lima", 2000, 19.0).unwrap()]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            single.fill_gaps("Lima"),
+            [(Climate::new("Lima", 2000, 19.0).unwrap(), false)]
+        );
+    }
+
+    #[test]
+    fn test_shift_year() {
+        let climate = Climate::new("Oslo", 2020, 3.4).unwrap();
+        assert_eq!(climate.shift_year(5), Climate::new("Oslo", 2025, 3.4));
+        assert_eq!(climate.shift_year(-20), Climate::new("Oslo", 2000, 3.4));
+        assert_eq!(climate.shift_year(0), Ok(climate.clone()));
+        assert_eq!(
+            climate.shift_year(-321),
+            Err(ParseClimateError::YearOutOfRange {
+                year: 1699,
+                min: Climate::MIN_YEAR,
+                max: Climate::MAX_YEAR
+            })
+        );
+        assert!(matches!(
+            climate.shift_year(i32::MIN),
+            Err(ParseClimateError::YearOutOfRange {
+                year: -2_147_481_628,
+                ..
+            })
+        ));
+        assert_eq!(
+            climate.shift_year(-2021).unwrap_err().to_string(),
+            format!(
+                "year -1 is outside the range {}..={}",
+                Climate::MIN_YEAR,
+                Climate::MAX_YEAR
+            )
+        );
+        let last = Climate::new_with_years("Oslo", u32::MAX, 3.4, &(0..=u32::MAX)).unwrap();
+        assert!(matches!(
+            last.shift_year(1),
+            Err(ParseClimateError::YearOutOfRange {
+                year: 4_294_967_296,
+                ..
+            })
+        ));
+        assert!(matches!(
+            climate.shift_year(81),
+            Err(ParseClimateError::YearOutOfRange { year: 2101, .. })
+        ));
+        assert_eq!(climate.year, 2020);
+    }
+
+    fn merge_inputs() -> (ClimateDataset, ClimateDataset) {
+        let left = [
+            ("Oslo", 2020, 3.4),
+            ("Oslo", 2021, 4.0),
+            ("Lima", 2020, 19.0),
+        ];
+        let right = [
+            ("Oslo", 2021, 4.4),
+            ("Lima", 2020, 19.0),
+            ("Cairo", 2020, 35.0),
+        ];
+        let dataset = |records: &[(&str, u32, f32)]| -> ClimateDataset {
+            records
+                .iter()
+                .map(|&(city, year, temp)| Climate::new(city, year, temp).unwrap())
+                .collect()
+        };
+        (dataset(&left), dataset(&right))
+    }
+
+    #[test]
+    fn test_merge_overlapping() {
+        let temps = |dataset: &ClimateDataset, city| -> Vec<(u32, f32)> {
+            dataset
+                .records_for(city)
+                .iter()
+                .map(|r| (r.year, r.temp))
+                .collect()
+        };
+        let (left, right) = merge_inputs();
+        let first = left
+            .clone()
+            .merge(right.clone(), MergePolicy::KeepFirst)
+            .unwrap();
+        assert_eq!(first.len(), 4);
+        assert_eq!(temps(&first, "Oslo"), [(2020, 3.4), (2021, 4.0)]);
+        assert_eq!(temps(&first, "Cairo"), [(2020, 35.0)]);
+
+        let last = left
+            .clone()
+            .merge(right.clone(), MergePolicy::KeepLast)
+            .unwrap();
+        assert_eq!(last.len(), 4);
+        assert_eq!(temps(&last, "Oslo"), [(2020, 3.4), (2021, 4.4)]);
+
+        let average = left
+            .clone()
+            .merge(right.clone(), MergePolicy::Average)
+            .unwrap();
+        assert_eq!(average.len(), 4);
+        assert_eq!(temps(&average, "Oslo"), [(2020, 3.4), (2021, 4.2)]);
+        assert_eq!(temps(&average, "Lima"), [(2020, 19.0)]);
+
+        // The identical Lima records aren't a conflict, the Oslo ones are.
+        let conflict = left.merge(right, MergePolicy::Error).unwrap_err();
+        assert_eq!(conflict.city, "Oslo");
+        assert_eq!(conflict.year, 2021);
+        assert_eq!(conflict.left, Climate::new("Oslo", 2021, 4.0).unwrap());
+        assert_eq!(conflict.right, Climate::new("Oslo", 2021, 4.4).unwrap());
+        assert_eq!(
+            conflict.to_string(),
+            "conflicting records for \"Oslo\" in 2021: Oslo,2021,4 and Oslo,2021,4.4"
+        );
+    }
+
+    #[test]
+    fn test_merge_disjoint() {
+        let (left, _) = merge_inputs();
+        let other: ClimateDataset = [
+            Climate::new("Oslo", 2019, 2.9).unwrap(),
+            Climate::new("Nuuk", 2020, -1.5).unwrap(),
+        ]
+        .into_iter()
+        .collect();
+        let mut expected: Vec<Climate> = left
+            .iter()
+            .chain(other.iter())
+            .flat_map(|(_, r)| r.to_vec())
+            .collect();
+        expected.sort();
+        for policy in [
+            MergePolicy::KeepFirst,
+            MergePolicy::KeepLast,
+            MergePolicy::Average,
+            MergePolicy::Error,
+        ] {
+            let merged = left.clone().merge(other.clone(), policy).unwrap();
+            let mut records: Vec<Climate> = merged.iter().flat_map(|(_, r)| r.to_vec()).collect();
+            records.sort();
+            assert_eq!(records, expected, "{:?}", policy);
+            assert_eq!(merged.years_for("Oslo"), [2019, 2020, 2021]);
+        }
+        let empty = ClimateDataset::new().merge(left.clone(), MergePolicy::Error);
+        assert_eq!(empty, Ok(left));
+    }
+
+    #[test]
+    fn test_merge_average_humidity() {
+        let left: ClimateDataset = [Climate::new("Oslo", 2020, 3.0)
+            .unwrap()
+            .with_humidity(80.0)
+            .unwrap()]
+        .into_iter()
+        .collect();
+        let right: ClimateDataset = [
+            Climate::new("Oslo", 2020, 4.0).unwrap(),
+            Climate::new("Oslo", 2020, 5.0)
+                .unwrap()
+                .with_humidity(70.0)
+                .unwrap(),
+        ]
+        .into_iter()
+        .collect();
+        let merged = left.merge(right, MergePolicy::Average).unwrap();
+        assert_eq!(
+            merged.records_for("Oslo"),
+            [Climate::new("Oslo", 2020, 4.0)
+                .unwrap()
+                .with_humidity(75.0)
+                .unwrap()]
+        );
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let dataset = sample_dataset();
+        let diff = dataset.diff(&dataset.clone(), 0.0);
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "");
+    }
+
+    #[test]
+    fn test_diff_added_city_and_removed_year() {
+        let old = sample_dataset();
+        let mut new: ClimateDataset = old
+            .records()
+            .filter(|r| !(r.city == "Oslo" && r.year == 2005))
+            .cloned()
+            .collect();
+        new.insert(Climate::new("Lima", 2020, 19.0).unwrap());
+        let diff = old.diff(&new, 0.1);
+        assert_eq!(
+            diff.only_in_self,
+            [Climate::new("Oslo", 2005, 2.8).unwrap()]
+        );
+        assert_eq!(
+            diff.only_in_other,
+            [Climate::new("Lima", 2020, 19.0).unwrap()]
+        );
+        assert!(diff.changed.is_empty());
+        assert_eq!(diff.to_string(), "+ Lima,2020,19\n- Oslo,2005,2.8\n");
+    }
+
+    #[test]
+    fn test_diff_epsilon_boundary() {
+        let dataset = |temp| -> ClimateDataset {
+            [Climate::new("Oslo", 2020, temp).unwrap()]
+                .into_iter()
+                .collect()
+        };
+        // Exactly representable, so the difference is exactly 0.5.
+        let (old, new) = (dataset(1.5), dataset(2.0));
+        assert!(old.diff(&new, 0.5).is_empty());
+        let diff = old.diff(&new, 0.25);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.to_string(), "~ Oslo,2020: 1.5 -> 2\n");
+
+        assert!(dataset(0.0).diff(&dataset(0.0), 0.0).is_empty());
+        assert_eq!(dataset(0.0).diff(&dataset(-0.0), 0.0).changed.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "temp_epsilon must not be negative or NaN")]
+    fn test_diff_negative_epsilon() {
+        sample_dataset().diff(&sample_dataset(), -1.0);
+    }
+
+    #[test]
+    fn test_climate_ref_parse() {
+        let line = String::from("Hong Kong,1999,25.7");
+        let parsed = ClimateRef::parse(&line).unwrap();
+        let city = match parsed.city {
+            Cow::Borrowed(city) => city,
+            Cow::Owned(_) => panic!("city was copied"),
+        };
+        let input = line.as_bytes().as_ptr_range();
+        assert!(input.contains(&city.as_ptr()));
+        assert_eq!(city, "Hong Kong");
+        assert_eq!(parsed.to_owned(), line.parse::<Climate>().unwrap());
+
+        for line in [
+            "",
+            "Oslo,1999",
+            "Oslo,1999,hot",
+            ",1999,2.5",
+            "Oslo,1999,90",
+        ] {
+            assert_eq!(
+                ClimateRef::parse(line).map(|r| r.to_owned()),
+                line.parse::<Climate>()
+            );
+        }
+    }
+
+    #[test]
+    fn test_summary_empty() {
+        assert_eq!(ClimateSummary::from_records(&[]), None);
+    }
+
+    #[test]
+    fn test_summary() {
+        let records: Vec<Climate> = sample_dataset().records().cloned().collect();
+        let summary = ClimateSummary::from_records(&records).unwrap();
+        assert_eq!(summary.count, 5);
+        assert_eq!(summary.years, 1990..=2020);
+        assert_eq!(summary.min_temp, 2.1);
+        assert_eq!(summary.max_temp, 25.7);
+        assert!((summary.avg_temp - 11.78).abs() < 1e-4);
+        assert_eq!(
+            summary.to_string(),
+            "5 records, 1990\u{2013}2020, temp min 2.1 / avg 11.8 / max 25.7"
+        );
+
+        let one = ClimateSummary::from_records(&records[..1]).unwrap();
+        assert_eq!(
+            one.to_string(),
+            "1 record, 1995\u{2013}1995, temp min 24.9 / avg 24.9 / max 24.9"
+        );
+    }
+
+    #[test]
+    fn test_bad_len_ignores_dropped_trailing_delimiter() {
+        let options = ParseOptions {
+            allow_trailing_delimiter: true,
+            ..ParseOptions::default()
+        };
+        let err = Climate::parse_with_options("Oslo,2020,", &options).unwrap_err();
+        assert_eq!(
+            err,
+            ParseClimateError::BadLen {
+                expected: 3..=4,
+                found: 2
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "incorrect number of fields: expected 3 or 4, found 2"
+        );
+    }
+
+    #[test]
+    fn test_comments_skipped_by_line_parsers() {
+        let input = "# recorded by hand\nOslo,2020,3.4\n  # Lima next\nLima,2010,19.2\n";
+        let expected = vec![
+            Climate::new("Oslo", 2020, 3.4).unwrap(),
+            Climate::new("Lima", 2010, 19.2).unwrap(),
+        ];
+        assert_eq!(
+            records_iter(input).collect::<Result<Vec<_>, _>>(),
+            Ok(expected.clone())
+        );
+        assert_eq!(ClimateTable::parse(input), Ok(expected.clone()));
+        assert_eq!(
+            ClimateTable::parse("# header next\nyear,city,temp\n# row\n2020,Oslo,3.4\n"),
+            Ok(expected[..1].to_vec())
+        );
+        assert_eq!(
+            ClimateTable::parse("# header next\nyear,city,temp\n2020,Oslo\n"),
+            Err((
+                3,
+                ParseClimateError::BadLen {
+                    expected: 3..=3,
+                    found: 2
+                }
+            ))
+        );
+        assert_eq!(
+            decode_chunks(input.as_bytes().chunks(5)),
+            expected.into_iter().map(Ok).collect::<Vec<_>>()
+        );
+
+        // `parse_climate_lines` is strict and rejects the comment.
+        let err = parse_climate_lines(input).unwrap_err();
+        assert_eq!(err.line, 1);
+        assert!(matches!(err.kind, ParseClimateError::BadLen { .. }));
+    }
+
+    #[test]
+    fn test_bom_skipped_by_line_parsers() {
+        let input = "\u{feff}Oslo,2020,3.4\nLima,2010,19.2\n";
+        let expected = vec![
+            Climate::new("Oslo", 2020, 3.4).unwrap(),
+            Climate::new("Lima", 2010, 19.2).unwrap(),
+        ];
+        assert_eq!(parse_climate_lines(input), Ok(expected.clone()));
+        assert_eq!(
+            records_iter(input).collect::<Result<Vec<_>, _>>(),
+            Ok(expected.clone())
+        );
+        assert_eq!(ClimateTable::parse(input), Ok(expected.clone()));
+        assert_eq!(
+            ClimateTable::parse("\u{feff}year,city,temp\n2020,Oslo,3.4\n"),
+            Ok(expected[..1].to_vec())
+        );
+        // The BOM's three bytes arrive in separate chunks.
+        let decoded = decode_chunks(input.as_bytes().chunks(1));
+        assert_eq!(
+            decoded,
+            expected.iter().cloned().map(Ok).collect::<Vec<_>>()
+        );
+        // Only a BOM at the very start is stripped.
+        let decoded = decode_chunks(["Oslo,2020,3.4\n\u{feff}Lima,2010,19.2\n".as_bytes()]);
+        assert_eq!(decoded[1].as_ref().unwrap().city, "\u{feff}Lima");
+    }
+
+    #[test]
+    fn test_bom_skipped_by_jsonl() {
+        let input = "\u{feff}{\"city\":\"Oslo\",\"year\":2020,\"temp\":3.4}\n";
+        let read: Result<Vec<_>, _> = read_jsonl(input.as_bytes()).collect();
+        assert_eq!(read.unwrap(), [Climate::new("Oslo", 2020, 3.4).unwrap()]);
+    }
+
+    #[test]
+    fn test_decimal_comma_error_keeps_field() {
+        let options = ParseOptions {
+            delimiter: ';',
+            number_format: NumberFormat::DecimalComma,
+            ..ParseOptions::default()
+        };
+        let field_of = |result: Result<Climate, ParseClimateError>| match result {
+            Err(ParseClimateError::ParseFloat { field, .. })
+            | Err(ParseClimateError::ParseHumidity { field, .. }) => field,
+            other => panic!("expected ParseFloat or ParseHumidity, got {:?}", other),
+        };
+        let err = Climate::parse_with_options("Oslo;2020;7,3,1", &options);
+        assert_eq!(field_of(err.clone()), "7,3,1");
+        assert!(err
+            .unwrap_err()
+            .to_string()
+            .starts_with("error parsing temperature \"7,3,1\""));
+        let err = Climate::parse_with_options("Oslo;2020;7,3;8,0,1", &options);
+        assert!(matches!(err, Err(ParseClimateError::ParseHumidity { .. })));
+        assert_eq!(field_of(err), "8,0,1");
+
+        let parser = ClimateParser::with_options(options);
+        let err = parser
+            .parse_borrowed("Oslo;2020;1,,5")
+            .map(|r| r.to_owned());
+        assert_eq!(field_of(err), "1,,5");
+    }
+
+    #[test]
+    fn test_parse_with_unit_suffix() {
+        assert_eq!(
+            Climate::parse_with_unit_suffix("Cairo,2010,30.1C"),
+            Climate::new("Cairo", 2010, 30.1)
+        );
+        assert_eq!(
+            Climate::parse_with_unit_suffix("Cairo,2010,30.1"),
+            Climate::new("Cairo", 2010, 30.1)
+        );
+        let cairo = Climate::parse_with_unit_suffix("Cairo,2010,86.2F").unwrap();
+        assert!((cairo.temp - 30.111_11).abs() < 1e-4);
+        assert_eq!(
+            Climate::parse_with_unit_suffix("Cairo,2010,30.1C,40"),
+            Climate::new("Cairo", 2010, 30.1).and_then(|c| c.with_humidity(40.0))
+        );
+
+        let err = Climate::parse_with_unit_suffix("Cairo,2010,30.1K").unwrap_err();
+        assert_eq!(err, ParseClimateError::UnknownUnit('K'));
+        assert_eq!(err.to_string(), "unknown temperature unit 'K'");
+        assert_eq!("Cairo,2010,30.1K".parse::<Climate>(), Err(err));
+        assert_eq!(
+            Climate::parse_with_unit_suffix("Cairo,2010,30.1X"),
+            Err(ParseClimateError::UnknownUnit('X'))
+        );
+        assert_eq!(
+            Climate::parse_with_unit_suffix(",2010,30.1K"),
+            Err(ParseClimateError::NoCity)
+        );
+    }
+
+    #[test]
+    fn test_retain_inliers_drops_emptied_city() {
+        // Below one sigma every record of an evenly split city is an
+        // outlier.
+        let mut dataset: ClimateDataset = [(2000, 0.0), (2001, 10.0), (2002, 0.0), (2003, 10.0)]
+            .into_iter()
+            .map(|(year, temp)| Climate::new("Oslo", year, temp).unwrap())
+            .collect();
+        assert_eq!(dataset.outliers(0.5).len(), 4);
+        dataset.retain_inliers(0.5);
+        assert!(dataset.is_empty());
+        assert!(dataset.cities().is_empty());
+        assert_eq!(dataset.report(), "");
+
+        let mut dataset = sample_dataset();
+        for year in [2000, 2001] {
+            dataset.insert(Climate::new("Lima", year, 10.0).unwrap());
+        }
+        for year in [2002, 2003] {
+            dataset.insert(Climate::new("Lima", year, 20.0).unwrap());
+        }
+        dataset.retain_inliers(0.5);
+        // Hong Kong has too few records to have outliers.
+        assert!(dataset.cities().contains(&"Hong Kong"));
+        assert!(!dataset.cities().contains(&"Lima"));
+        assert!(dataset.records_for("Lima").is_empty());
+    }
+
+    #[test]
+    fn test_from_json_reader() {
+        let oslo = Climate::new("Oslo", 2020, 3.4).unwrap();
+        let json = r#" { "station" : {"id": [1, -2.5e3, {"x": null}], "ok": true},
+            "city": "Oslo", "year": 2020, "temp": 3.4, "humidity": null } "#;
+        assert_eq!(Climate::from_json(json), Ok(oslo.clone()));
+        assert_eq!(
+            Climate::from_json(r#"{"city":"São \"P\"\/😀","year":2001,"temp":0}"#)
+                .unwrap()
+                .city,
+            "São \"P\"/😀"
+        );
+        assert_eq!(
+            Climate::from_json(r#"{"city":"Oslo","year":2020,"temp":3.4,"humidity":80}"#),
+            oslo.clone().with_humidity(80.0)
+        );
+        assert_eq!(
+            Climate::from_json(r#"{"city":null,"year":2020,"temp":3.4}"#),
+            Err(ParseClimateError::MissingField("city"))
+        );
+        let invalid = |s: &str| match Climate::from_json(s) {
+            Err(ParseClimateError::InvalidJson(message)) => message,
+            other => panic!("expected InvalidJson for {:?}, got {:?}", s, other),
+        };
+        assert_eq!(invalid(""), "expected a value at column 1");
+        assert_eq!(invalid("[]"), "expected an object");
+        assert_eq!(
+            invalid(r#"{"city":"Oslo","city":"Lima"}"#),
+            "duplicate key \"city\""
+        );
+        assert_eq!(
+            invalid(r#"{"city":"Oslo","year":2020.0,"temp":3.4}"#),
+            "\"year\" must be an integer that fits a u32"
+        );
+        assert_eq!(
+            invalid(r#"{"city":"Oslo","year":2020,"temp":true}"#),
+            "\"temp\" must be a number"
+        );
+        assert_eq!(
+            invalid(r#"{"city":"Oslo"} x"#),
+            "trailing characters at column 17"
+        );
+        assert_eq!(invalid(r#"{"year":01}"#), "invalid number at column 9");
+        assert_eq!(invalid(r#"{"year":1.}"#), "invalid number at column 9");
+        assert_eq!(invalid(r#"{"city":"a\qb"}"#), "invalid escape at column 13");
+        assert_eq!(
+            invalid(r#"{"city":"\ud83d"}"#),
+            "unpaired surrogate at column 16"
+        );
+        assert_eq!(
+            invalid("{\"city\":\"a\nb\"}"),
+            "control character in string at column 12"
+        );
+        assert_eq!(invalid(r#"{"city" "Oslo"}"#), "expected `:` at column 9");
+        assert_eq!(invalid(r#"{"city":"Oslo""#), "expected `,` at column 15");
+        let deep = format!("{{\"x\":{}{}}}", "[".repeat(100), "]".repeat(100));
+        assert!(invalid(&deep).starts_with("nesting too deep"));
+    }
+
+    #[test]
+    fn test_to_json_array_reads_back() {
+        let records = [
+            Climate::new("The \"Big\" Apple\n", 2001, -3.5).unwrap(),
+            Climate::new("Oslo", 2020, 3.4)
+                .unwrap()
+                .with_humidity(80.25)
+                .unwrap(),
+        ];
+        let array = to_json_array(&records);
+        let elements = match JsonReader::parse(&array) {
+            Ok(JsonValue::Array(elements)) => elements,
+            other => panic!("expected an array, got {:?}", other),
+        };
+        assert_eq!(elements.len(), records.len());
+        for (element, record) in elements.iter().zip(&records) {
+            let json = record.to_json();
+            assert_eq!(JsonReader::parse(&json).as_ref(), Ok(element));
+            assert_eq!(Climate::from_json(&json).as_ref(), Ok(record));
+        }
+    }
+
+    #[test]
+    fn test_bom_only_is_empty_input() {
+        for input in ["\u{feff}", "\u{feff}\n", "\u{feff}\r\n"] {
+            assert_eq!(parse_climate_lines(input), parse_climate_lines(""));
+            assert_eq!(parse_records(input), Ok(vec![]));
+            assert!(validate_all(input).is_empty());
+            assert_eq!(records_iter(input).count(), 0);
+            assert_eq!(ClimateTable::parse(input), Ok(vec![]));
+            assert_eq!(parse_with_header(input), parse_with_header(""));
+            assert_eq!(decode_chunks([input.as_bytes()]), []);
+            assert!(ClimateReader::new(input.as_bytes()).next().is_none());
+            assert!(read_jsonl(input.as_bytes()).next().is_none());
+        }
+    }
+
+    #[test]
+    fn test_positioned_error_quoted_city() {
+        let err = parse_climate_lines("\"Paris, France\",20x0,1.0").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 16));
+        let err =
+            parse_climate_lines("Oslo,2020,3.4\n\"Zürich, \"\"CH\"\"\",2001,abc").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 22));
+        assert!(err.to_string().starts_with("line 2, column 22: "));
+    }
+
+    #[test]
+    fn test_fill_gaps_last_year() {
+        // `ParseOptions::years` can let through years up to `u32::MAX`.
+        let options = ParseOptions {
+            years: 0..=u32::MAX,
+            ..ParseOptions::default()
+        };
+        let dataset: ClimateDataset = [
+            "Oslo,4294967293,1.0",
+            "Oslo,4294967295,3.0",
+            "Oslo,4294967295,5.0",
+        ]
+        .iter()
+        .map(|line| Climate::parse_with_options(line, &options).unwrap())
+        .collect();
+        let filled: Vec<(u32, f32, bool)> = dataset
+            .fill_gaps("Oslo")
+            .into_iter()
+            .map(|(r, interpolated)| (r.year, r.temp, interpolated))
+            .collect();
+        assert_eq!(
+            filled,
+            [
+                (u32::MAX - 2, 1.0, false),
+                (u32::MAX - 1, 2.5, true),
+                (u32::MAX, 3.0, false),
+                (u32::MAX, 5.0, false),
+            ]
+        );
+    }
+}